@@ -0,0 +1,57 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Error};
+use clap::ArgMatches;
+use log::error;
+
+use rendr::blueprint::source::locator;
+use rendr::blueprint::Blueprint;
+use rendr::blueprint::RendrConfig;
+use rendr::blueprint::Values;
+use rendr::project::Project;
+
+use crate::source_options::SourceOptions;
+use crate::upgrade::rendered_blueprint;
+
+pub fn add(args: &ArgMatches) -> Result<(), Error> {
+    let working_dir: PathBuf =
+        env::current_dir().map_err(|e| anyhow!("error determining working directory: {}", e))?;
+    let dir = Path::new(
+        args.value_of("dir")
+            .unwrap_or(working_dir.to_str().unwrap()),
+    );
+    let values = match args.is_present("value") {
+        true => Values::from(args.values_of("value").unwrap()),
+        false => Values::new(),
+    };
+    let dry_run = args.is_present("dry-run");
+
+    let SourceOptions { auth, cache, .. } = SourceOptions::from_args(args)?;
+
+    let rendr_file: PathBuf = dir.join(Path::new(".rendr.yaml"));
+    if !rendr_file.exists() {
+        error!("This directory does not appear to be a Rendr project: no .rendr.yaml file found");
+        error!("  Expected file at {}", rendr_file.display());
+        return Err(anyhow!("Project metadata not available"));
+    }
+
+    let yaml = fs::read_to_string(rendr_file)?;
+    let config: RendrConfig = serde_yaml::from_str(&yaml)?;
+
+    let (blueprint, _) =
+        rendered_blueprint(dir, &config.source, &config.pinned_source(), &auth, &cache)?;
+    let project = Project::new(&dir, blueprint)?;
+
+    let addon_source = locator(
+        args.value_of("blueprint").unwrap(),
+        args.value_of("blueprint-path"),
+        args.value_of("ref"),
+    );
+    let addon = Blueprint::with_cache(&addon_source, Some(auth), &cache)?;
+
+    project
+        .add(&addon, values, dry_run)
+        .map_err(|e| anyhow!("error adding blueprint: {}", e))
+}
@@ -0,0 +1,57 @@
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Error};
+use chrono::{DateTime, Local};
+use clap::ArgMatches;
+use log::info;
+
+use rendr::blueprint::cache::Cache;
+
+pub fn cache(args: &ArgMatches) -> Result<(), Error> {
+    match args.subcommand() {
+        ("list", Some(args)) => list(args),
+        ("prune", Some(args)) => prune(args),
+        _ => panic!("unknown subcommand"),
+    }
+}
+
+fn list(_args: &ArgMatches) -> Result<(), Error> {
+    let cache = Cache::default();
+
+    for entry in cache.entries()? {
+        println!("{}", entry.url);
+        println!("  path: {}", entry.path.display());
+        if let Some(last_used) = entry.last_used {
+            let last_used: DateTime<Local> = last_used.into();
+            println!("  last used: {}", last_used.format("%Y-%m-%d %H:%M"));
+        }
+    }
+
+    Ok(())
+}
+
+fn prune(args: &ArgMatches) -> Result<(), Error> {
+    let cache = Cache::default();
+
+    // Without a maximum age, everything goes.
+    let cutoff = match args.value_of("older-than") {
+        Some(days) => {
+            let days: u64 = days
+                .parse()
+                .map_err(|_| anyhow!("invalid number of days: {}", days))?;
+            SystemTime::now()
+                .checked_sub(Duration::from_secs(days * 24 * 60 * 60))
+                .unwrap_or(SystemTime::UNIX_EPOCH)
+        }
+        None => SystemTime::now(),
+    };
+
+    for entry in cache.entries()? {
+        if entry.last_used.is_none_or(|t| t < cutoff) {
+            info!("Removing {} from the cache", entry.url);
+            cache.remove(&entry)?;
+        }
+    }
+
+    Ok(())
+}
@@ -6,9 +6,11 @@ use anyhow::{anyhow, Error};
 use clap::ArgMatches;
 use log::{debug, error, info};
 
-use rendr::blueprint::{Blueprint, BlueprintAuth, RendrConfig};
+use rendr::blueprint::{Blueprint, RendrConfig};
 use rendr::project::Project;
 
+use crate::source_options::SourceOptions;
+
 pub fn check(args: &ArgMatches) -> Result<(), Error> {
     // Parse CLI arguments.
     let working_dir =
@@ -18,10 +20,7 @@ pub fn check(args: &ArgMatches) -> Result<(), Error> {
             .unwrap_or(working_dir.to_str().unwrap()),
     );
 
-    let username = args.value_of("user").map(|s| s.to_string());
-    let password = args.value_of("password").map(|s| s.to_string());
-    let ssh_key = args.value_of("ssh-key").map(|s| s.to_string());
-    let auth = BlueprintAuth::new(username, password, ssh_key);
+    let SourceOptions { auth, cache, .. } = SourceOptions::from_args(args)?;
 
     let rendr_file: PathBuf = dir.join(Path::new(".rendr.yaml"));
     if !rendr_file.exists() {
@@ -40,8 +39,12 @@ pub fn check(args: &ArgMatches) -> Result<(), Error> {
     );
     // let blueprint = Blueprint::new(config.source.as_str(), Some(auth));
     let blueprint = match relative_source.exists() {
-        true => Blueprint::new(relative_source.as_os_str().to_str().unwrap(), Some(auth)),
-        false => Blueprint::new(config.source.as_str(), Some(auth)),
+        true => Blueprint::with_cache(
+            relative_source.as_os_str().to_str().unwrap(),
+            Some(auth),
+            &cache,
+        ),
+        false => Blueprint::with_cache(&config.pinned_source(), Some(auth), &cache),
     };
 
     // Attempt to parse the provided project.
@@ -6,13 +6,20 @@ use std::path::Path;
 
 use anyhow::{anyhow, Error};
 use clap::ArgMatches;
+use indexmap::IndexMap;
 use log::{debug, info};
 
-use rendr::blueprint::{BlueprintMetadata, ValueSpec};
+use rendr::blueprint::version::BlueprintVersion;
+use rendr::blueprint::{BlueprintMetadata, Value, ValueSpec};
+use rendr::config::UserConfig;
 
 pub fn create(args: &ArgMatches) -> Result<(), Error> {
     let name = args.value_of("name").unwrap();
-    let author = args.value_of("author").unwrap_or("");
+    let user_config = UserConfig::load()?;
+    let author = args
+        .value_of("author")
+        .or(user_config.author.as_deref())
+        .unwrap_or("");
     let description = args
         .value_of("description")
         .unwrap_or("A simple blueprint for Rendr");
@@ -44,25 +51,41 @@ pub fn create(args: &ArgMatches) -> Result<(), Error> {
         description: String::from("The app name"),
         required: true,
         default: Option::None,
+        default_from_env: None,
+        default_command: None,
+        secret: false,
     };
     let value2 = ValueSpec {
         name: String::from("magic_number"),
         description: String::from("The magic number"),
         required: false,
-        default: Option::Some(String::from("42")),
+        default: Option::Some(Value::from("42")),
+        default_from_env: None,
+        default_command: None,
+        secret: false,
     };
     let values = vec![value1, value2];
 
     let config = BlueprintMetadata {
         name: String::from(name),
-        version: 1,
+        version: BlueprintVersion::new(1, 0, 0),
         author: String::from(author),
         description: String::from(description),
         editable_templates: false,
         values: values,
-        exclusions: Vec::new(),
-        git_init: false,
+        presets: IndexMap::new(),
+        verbatim: Vec::new(),
+        ignore: Vec::new(),
+        git_init: Some(false),
         upgrades: Vec::new(),
+        tag_pattern: None,
+        min_rendr_version: None,
+        max_rendr_version: None,
+        extends: None,
+        remove: Vec::new(),
+        include_if: IndexMap::new(),
+        case_insensitive_patterns: false,
+        variants: Vec::new(),
     };
 
     let metadata = serde_yaml::to_string(&config)?;
@@ -1,7 +1,6 @@
-use std::collections::HashMap;
+use std::env;
 use std::error::Error;
 use std::fs;
-use std::io::{self, Write};
 use std::path::Path;
 use std::sync::mpsc::channel;
 use std::time::Duration;
@@ -10,52 +9,77 @@ use clap::ArgMatches;
 use log::{debug, error, info};
 use notify::DebouncedEvent;
 use notify::{watcher, RecursiveMode, Watcher};
-use text_io::read;
 
-use rendr::blueprint::{Blueprint, BlueprintAuth, ValueSpec};
+use rendr::blueprint::source::locator;
+use rendr::blueprint::{Blueprint, Values, PRESET_VALUE, PROJECT_NAME_VALUE};
+use rendr::config::{GitInitPolicy, UserConfig};
 use rendr::templating;
 
+use crate::source_options::SourceOptions;
+
 type DynError = Box<dyn Error>;
 
 pub fn init(args: &ArgMatches) -> Result<(), DynError> {
     let blueprint_path = args.value_of("blueprint").unwrap();
     let scaffold_path = Path::new(args.value_of("dir").unwrap_or("."));
 
-    let username = args.value_of("user").map(|s| s.to_string());
-    let password = args.value_of("password").map(|s| s.to_string());
-    let ssh_key = args.value_of("ssh-key").map(|s| s.to_string());
-    let auth = BlueprintAuth::new(username, password, ssh_key);
-
-    let blueprint = Blueprint::new(blueprint_path, Some(auth))?;
+    let SourceOptions {
+        user_config,
+        auth,
+        cache,
+    } = SourceOptions::from_args(args)?;
 
-    // Time to parse values. Let's start by collecting the defaults.
-    let mut values: HashMap<&str, &str> = blueprint.default_values().collect();
-
-    // If some values were provided via CLI arguments, merge those in.
-    if let Some(cli_values) = args.values_of("value") {
-        let cli_values: Result<Vec<_>, _> = cli_values.map(parse_value).collect();
-        values.extend(cli_values?);
+    let blueprint_path = locator(
+        blueprint_path,
+        args.value_of("blueprint-path"),
+        args.value_of("ref"),
+    );
+    let blueprint = Blueprint::with_cache(&blueprint_path, Some(auth), &cache)?;
+
+    // Time to parse values. Let's start with the ones provided via CLI arguments.
+    let mut cli_values = Values::new();
+    if let Some(args) = args.values_of("value") {
+        for cli_value in args {
+            let (name, value) = parse_value(cli_value)?;
+            cli_values.insert(name, value);
+        }
     }
 
-    // Figure out which required values are still missing.
-    let missing_values = blueprint
-        .required_values()
-        .filter(|v| values.get::<str>(&v.name).is_none());
+    // The project name defaults to the name of the output directory.
+    let project_name = match args.value_of("name") {
+        Some(name) => Some(name.to_string()),
+        None => project_dir_name(scaffold_path),
+    };
+    if let Some(name) = project_name {
+        cli_values.insert(PROJECT_NAME_VALUE, name);
+    }
 
-    // Prompt for the missing values and collect them.
-    let prompt_values_owned: Vec<_> = prompt_for_values(missing_values).collect();
+    // A preset fills in values that weren't provided via CLI arguments.
+    let mut provided = Values::new();
+    if let Some(preset) = args.value_of("preset") {
+        let preset_values = blueprint.preset(preset).ok_or_else(|| {
+            format!(
+                "unknown preset `{}` (available presets: {})",
+                preset,
+                blueprint.preset_names().collect::<Vec<_>>().join(", ")
+            )
+        })?;
+        provided.merge(preset_values);
+        provided.insert(PRESET_VALUE, preset);
+    }
+    provided.merge(&cli_values);
 
-    // Merge the values from prompts in.
-    let prompt_values: Vec<_> = prompt_values_owned
-        .iter()
-        .map(|(k, v)| (*k, v.as_str()))
-        .collect();
-    values.extend(prompt_values);
+    // Fill in the blueprint defaults, and prompt for anything that's missing.
+    // Default commands run in the project directory, so it's created first.
+    if !scaffold_path.is_dir() {
+        fs::create_dir_all(scaffold_path)?;
+    }
+    let values = blueprint.complete_values(scaffold_path, provided)?;
 
-    init_scaffold(&blueprint, args, &values)?;
+    init_scaffold(&blueprint, args, &values, &user_config)?;
 
     if args.is_present("watch") {
-        watch(&blueprint, scaffold_path, args, &values)?;
+        watch(&blueprint, scaffold_path, args, &values, &user_config)?;
     }
 
     Ok(())
@@ -64,7 +88,8 @@ pub fn init(args: &ArgMatches) -> Result<(), DynError> {
 fn init_scaffold(
     blueprint: &Blueprint,
     args: &ArgMatches,
-    values: &HashMap<&str, &str>,
+    values: &Values,
+    user_config: &UserConfig,
 ) -> Result<(), DynError> {
     // Parse CLI arguments.
     let output_dir = Path::new(args.value_of("dir").unwrap_or("."));
@@ -77,15 +102,19 @@ fn init_scaffold(
         &output_dir
     );
 
-    let engine = templating::Tmplpp::new();
-    blueprint.render(
-        &engine,
-        &values.into(),
-        &output_dir,
+    // Without a flag, the user config decides whether to initialize Git.
+    let (git_init, no_git_init) = match (
         args.is_present("git-init"),
         args.is_present("no-git-init"),
-        dry_run,
-    )?;
+        user_config.git_init,
+    ) {
+        (false, false, GitInitPolicy::Always) => (true, false),
+        (false, false, GitInitPolicy::Never) => (false, true),
+        (git_init, no_git_init, _) => (git_init, no_git_init),
+    };
+
+    let engine = templating::Tmplpp::new();
+    blueprint.render(&engine, values, output_dir, git_init, no_git_init, dry_run)?;
     info!("Success. Enjoy!");
 
     Ok(())
@@ -95,7 +124,8 @@ fn watch(
     blueprint: &Blueprint,
     scaffold_path: impl AsRef<Path> + Copy,
     args: &ArgMatches,
-    values: &HashMap<&str, &str>,
+    values: &Values,
+    user_config: &UserConfig,
 ) -> Result<(), DynError> {
     info!("Watching for blueprint changes...");
 
@@ -124,7 +154,7 @@ fn watch(
                         info!("");
                         info!("Blueprint changed! Recreating scaffold...");
                         rm_all(scaffold_path.as_ref())?;
-                        if let Err(e) = init_scaffold(blueprint, args, values) {
+                        if let Err(e) = init_scaffold(blueprint, args, values, user_config) {
                             error!("{}", e);
                         }
                     }
@@ -151,18 +181,15 @@ fn rm_all(p: &Path) -> Result<(), DynError> {
     Ok(())
 }
 
-type ValueFromPrompt<'s> = (&'s str, String);
-
-fn prompt_for_values<'s>(
-    values: impl Iterator<Item = &'s ValueSpec>,
-) -> impl Iterator<Item = ValueFromPrompt<'s>> {
-    values.map(prompt_for_value)
-}
+/// The name of the directory the project is rendered into, which may not
+/// exist yet.
+fn project_dir_name(path: &Path) -> Option<String> {
+    let path = match path.canonicalize() {
+        Ok(path) => path,
+        Err(_) => env::current_dir().ok()?.join(path),
+    };
 
-fn prompt_for_value(value: &ValueSpec) -> ValueFromPrompt<'_> {
-    print!("{}: ", value.description);
-    io::stdout().flush().unwrap();
-    (&value.name, read!("{}\n"))
+    path.file_name().map(|n| n.to_string_lossy().to_string())
 }
 
 fn parse_value(s: &str) -> Result<(&str, &str), String> {
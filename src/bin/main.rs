@@ -1,8 +1,12 @@
+mod add;
+mod cache;
 mod check;
 mod create_blueprint;
 mod info;
 mod init;
+mod source_options;
 mod upgrade;
+mod values;
 
 use std::env;
 use std::error::Error;
@@ -73,11 +77,14 @@ fn run_app() -> Result<(), DynError> {
     let matches = App::from_yaml(yaml).version(crate_version!()).get_matches();
 
     match matches.subcommand() {
+        ("add", Some(args)) => add::add(args)?,
+        ("cache", Some(args)) => cache::cache(args)?,
         ("create", Some(args)) => init::init(args)?,
         ("create-blueprint", Some(args)) => create_blueprint::create(args)?,
         ("check", Some(args)) => check::check(args)?,
         ("info", Some(args)) => info::info(args)?,
         ("upgrade", Some(args)) => upgrade::upgrade(args)?,
+        ("values", Some(args)) => values::values(args)?,
         _ => panic!("unknown subcommand"),
     }
 
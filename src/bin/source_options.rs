@@ -0,0 +1,44 @@
+use std::path::PathBuf;
+
+use clap::ArgMatches;
+
+use rendr::blueprint::cache::Cache;
+use rendr::blueprint::BlueprintAuth;
+use rendr::config::{ConfigError, UserConfig};
+
+/// The options for fetching blueprints, which all commands that load a
+/// blueprint share.
+pub struct SourceOptions {
+    pub user_config: UserConfig,
+    pub auth: BlueprintAuth,
+    pub cache: Cache,
+}
+
+impl SourceOptions {
+    /// Reads the options from the CLI arguments. The user config provides
+    /// defaults for the auth options.
+    pub fn from_args(args: &ArgMatches) -> Result<Self, ConfigError> {
+        let user_config = UserConfig::load()?;
+
+        let username = args
+            .value_of("user")
+            .map(|s| s.to_string())
+            .or_else(|| user_config.auth.user.clone());
+        let password = args.value_of("password").map(|s| s.to_string());
+        let ssh_key = args
+            .value_of("ssh-key")
+            .map(|s| s.to_string())
+            .or_else(|| user_config.ssh_key());
+        let auth = BlueprintAuth::new(username, password, ssh_key);
+
+        let cache = Cache::new(Cache::default_path(), args.is_present("offline"))
+            .accept_new_host_keys(args.is_present("accept-new-host-key"))
+            .with_ca_bundle(args.value_of("ca-bundle").map(PathBuf::from));
+
+        Ok(SourceOptions {
+            user_config,
+            auth,
+            cache,
+        })
+    }
+}
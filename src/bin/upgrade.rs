@@ -6,11 +6,16 @@ use anyhow::{anyhow, Error};
 use clap::ArgMatches;
 use log::{debug, error};
 
+use rendr::blueprint::cache::Cache;
+use rendr::blueprint::source::locator;
+use rendr::blueprint::version::BlueprintVersion;
 use rendr::blueprint::Blueprint;
 use rendr::blueprint::BlueprintAuth;
 use rendr::blueprint::RendrConfig;
 use rendr::blueprint::Values;
-use rendr::project::Project;
+use rendr::project::{Project, UpgradeError, UpgradeTarget};
+
+use crate::source_options::SourceOptions;
 
 pub fn upgrade(args: &ArgMatches) -> Result<(), Error> {
     let working_dir: PathBuf =
@@ -26,10 +31,7 @@ pub fn upgrade(args: &ArgMatches) -> Result<(), Error> {
     };
     let dry_run = args.is_present("dry-run");
 
-    let username = args.value_of("user").map(|s| s.to_string());
-    let password = args.value_of("password").map(|s| s.to_string());
-    let ssh_key = args.value_of("ssh-key").map(|s| s.to_string());
-    let auth = BlueprintAuth::new(username, password, ssh_key);
+    let SourceOptions { auth, cache, .. } = SourceOptions::from_args(args)?;
 
     let rendr_file: PathBuf = dir.join(Path::new(".rendr.yaml"));
     if !rendr_file.exists() {
@@ -41,21 +43,103 @@ pub fn upgrade(args: &ArgMatches) -> Result<(), Error> {
     let yaml = fs::read_to_string(rendr_file)?;
     let config: RendrConfig = serde_yaml::from_str(&yaml)?;
 
-    let relative_source = dir.join(config.source.clone());
+    let (blueprint, source) =
+        rendered_blueprint(dir, &config.source, &config.pinned_source(), &auth, &cache)?;
+
+    let mut project = Project::new(&dir, blueprint)?;
+
+    // Add-ons are upgraded on their own, from their own source.
+    let addon = match args.value_of("addon") {
+        Some(name) => {
+            let addon = config
+                .addon(name)
+                .ok_or_else(|| UpgradeError::UnknownAddon(name.to_string()))?;
+            let (current, source) =
+                rendered_blueprint(dir, &addon.source, &addon.pinned_source(), &auth, &cache)?;
+            Some((name, current, source, addon))
+        }
+        None => None,
+    };
+    let (current, source, source_path, version_range) = match &addon {
+        Some((_, current, source, addon)) => (
+            current,
+            source,
+            addon.source_path.as_deref(),
+            &addon.version_range,
+        ),
+        None => (
+            project.blueprint(),
+            &source,
+            config.source_path.as_deref(),
+            &config.version_range,
+        ),
+    };
+
+    // Upgrade to the requested ref, or to the latest tag of the blueprint.
+    let new_source = match (blueprint_source, args.value_of("blueprint-path")) {
+        (Some(source), path) => locator(source, path, None),
+        (None, Some(path)) => locator(source, Some(path), None),
+        (None, None) => locator(source, source_path, None),
+    };
+    let to_version = args
+        .value_of("to-version")
+        .map(str::parse::<BlueprintVersion>)
+        .transpose()?;
+    let tag_pattern = match args.value_of("tag-pattern") {
+        Some(pattern) => pattern.parse()?,
+        None => current.metadata.tag_pattern.clone().unwrap_or_default(),
+    };
+    let target = UpgradeTarget {
+        git_ref: args.value_of("ref"),
+        version: to_version,
+        tag_pattern,
+        range: match args.is_present("allow-major") {
+            true => None,
+            false => version_range.clone(),
+        },
+    };
+    let new_blueprint = target.blueprint(&new_source, Some(auth), &cache)?;
+    let allow_major = args.is_present("allow-major");
+
+    match addon {
+        Some((name, ..)) => project
+            .upgrade_addon(name, &new_blueprint, values, dry_run, allow_major)
+            .map_err(|e| anyhow!("error upgrading add-on: {}", e)),
+        None => project
+            .upgrade(&new_blueprint, values, dry_run, allow_major)
+            .map_err(|e| anyhow!("error upgrading blueprint: {}", e)),
+    }
+}
+
+/// The blueprint a project, or one of its add-ons, was rendered from, and the
+/// source to upgrade it from. Local blueprints are found relative to the
+/// project, others at the pinned commit.
+pub fn rendered_blueprint(
+    dir: &Path,
+    source: &str,
+    pinned_source: &str,
+    auth: &BlueprintAuth,
+    cache: &Cache,
+) -> Result<(Blueprint, String), Error> {
+    let relative_source = dir.join(source);
 
     debug!(
         "Locating blueprint source, checking if relative source exists: {}",
         relative_source.display()
     );
-    // let blueprint = Blueprint::new(config.source.as_str(), Some(auth));
     let blueprint = match relative_source.exists() {
-        true => Blueprint::new(relative_source.as_os_str().to_str().unwrap(), Some(auth)),
-        false => Blueprint::new(config.source.as_str(), Some(auth)),
+        true => {
+            let source = relative_source.as_os_str().to_str().unwrap().to_string();
+            (
+                Blueprint::with_cache(&source, Some(auth.clone()), cache)?,
+                source,
+            )
+        }
+        false => (
+            Blueprint::with_cache(pinned_source, Some(auth.clone()), cache)?,
+            source.to_string(),
+        ),
     };
 
-    let mut project = Project::new(&dir, blueprint.unwrap())?;
-
-    project
-        .upgrade(blueprint_source, values, dry_run)
-        .map_err(|e| anyhow!("error upgrading blueprint: {}", e))
+    Ok(blueprint)
 }
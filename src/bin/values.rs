@@ -0,0 +1,97 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Error};
+use clap::ArgMatches;
+use log::{debug, error, info};
+
+use rendr::blueprint::encryption::LocalKey;
+use rendr::blueprint::{RendrConfig, Values};
+
+pub fn values(args: &ArgMatches) -> Result<(), Error> {
+    match args.subcommand() {
+        ("encrypt", Some(args)) => encrypt(args),
+        ("decrypt", Some(args)) => decrypt(args),
+        _ => panic!("unknown subcommand"),
+    }
+}
+
+fn encrypt(args: &ArgMatches) -> Result<(), Error> {
+    let rendr_file = rendr_file(args)?;
+    let mut config = load_config(&rendr_file)?;
+    let cli_values = match args.is_present("value") {
+        true => Values::from(args.values_of("value").unwrap()),
+        false => Values::new(),
+    };
+
+    let key_path = LocalKey::default_path()?;
+    if !key_path.exists() {
+        info!("Generating a new encryption key at {}", key_path.display());
+    }
+    let key = LocalKey::load_or_generate(&key_path)?;
+
+    for name in args.values_of("name").unwrap() {
+        let plaintext = cli_values.get_value(name);
+
+        let stored = config.values();
+        match (stored.contains(name), stored.is_encrypted(name), plaintext) {
+            (false, _, None) => return Err(anyhow!("no value named `{}` in the project", name)),
+            (_, true, None) => {
+                info!("Value `{}` is already encrypted", name);
+                continue;
+            }
+            _ => {}
+        }
+
+        debug!("Encrypting value `{}`", name);
+        config.values_mut().encrypt(name, plaintext, &key)?;
+    }
+
+    config
+        .save(&rendr_file)
+        .map_err(|e| anyhow!("error writing {}: {}", rendr_file.display(), e))
+}
+
+fn decrypt(args: &ArgMatches) -> Result<(), Error> {
+    let rendr_file = rendr_file(args)?;
+    let mut config = load_config(&rendr_file)?;
+
+    let key = LocalKey::load(LocalKey::default_path()?)?;
+
+    for name in args.values_of("name").unwrap() {
+        if !config.values().contains(name) {
+            return Err(anyhow!("no value named `{}` in the project", name));
+        }
+
+        debug!("Decrypting value `{}`", name);
+        config.values_mut().decrypt(name, &key)?;
+    }
+
+    config
+        .save(&rendr_file)
+        .map_err(|e| anyhow!("error writing {}: {}", rendr_file.display(), e))
+}
+
+fn rendr_file(args: &ArgMatches) -> Result<PathBuf, Error> {
+    let working_dir =
+        env::current_dir().map_err(|e| anyhow!("error determining working directory: {}", e))?;
+    let dir = Path::new(
+        args.value_of("dir")
+            .unwrap_or(working_dir.to_str().unwrap()),
+    );
+
+    let rendr_file = dir.join(Path::new(".rendr.yaml"));
+    if !rendr_file.exists() {
+        error!("This directory does not appear to be a Rendr project: no .rendr.yaml file found");
+        error!("  Expected file at {}", rendr_file.display());
+        return Err(anyhow!("project metadata not available"));
+    }
+
+    Ok(rendr_file)
+}
+
+fn load_config(rendr_file: &PathBuf) -> Result<RendrConfig, Error> {
+    RendrConfig::load(rendr_file)
+        .map_err(|e| anyhow!("error reading {}: {}", rendr_file.display(), e))?
+        .ok_or_else(|| anyhow!("project metadata not available"))
+}
@@ -0,0 +1,261 @@
+//! Extraction of blueprint archives (`.tar.gz`, `.tgz` and `.zip`). Each entry
+//! is checked before it's extracted, so that nothing ends up outside the
+//! target directory.
+
+use std::fs::{self, File};
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use log::debug;
+use tar::EntryType;
+use zip::ZipArchive;
+
+use super::source::BlueprintSourceError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveKind {
+    TarGz,
+    Zip,
+}
+
+impl ArchiveKind {
+    /// The kind of archive a file or URL points to, judging by its extension.
+    pub fn of(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else {
+            None
+        }
+    }
+
+    /// The name a downloaded archive of this kind is saved under.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            ArchiveKind::TarGz => "blueprint.tar.gz",
+            ArchiveKind::Zip => "blueprint.zip",
+        }
+    }
+}
+
+/// Extracts the archive into the target directory, and returns the directory
+/// containing the blueprint's `metadata.yaml`: either the target directory
+/// itself or the archive's single top-level folder.
+pub fn extract(
+    archive: &Path,
+    kind: ArchiveKind,
+    target: &Path,
+) -> Result<PathBuf, BlueprintSourceError> {
+    debug!("Extracting {} into {}", archive.display(), target.display());
+
+    let file = File::open(archive).map_err(BlueprintSourceError::LocalReadError)?;
+    match kind {
+        ArchiveKind::TarGz => extract_tar_gz(file, target)?,
+        ArchiveKind::Zip => extract_zip(file, target)?,
+    }
+
+    blueprint_root(target)
+}
+
+/// Extracts a `.tar.gz` archive, checking each entry before it's written.
+/// Links could point anywhere, so they're not allowed at all.
+fn extract_tar_gz(file: File, target: &Path) -> Result<(), BlueprintSourceError> {
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+    archive.set_preserve_permissions(false);
+
+    for entry in archive.entries().map_err(archive_error)? {
+        let mut entry = entry.map_err(archive_error)?;
+        let name = entry.path().map_err(archive_error)?.display().to_string();
+        check_entry(&name)?;
+
+        match entry.header().entry_type() {
+            EntryType::Regular | EntryType::Continuous | EntryType::Directory => {}
+            _ => return Err(BlueprintSourceError::UnsafeArchiveEntry(name)),
+        }
+
+        entry.unpack_in(target).map_err(archive_error)?;
+    }
+
+    Ok(())
+}
+
+/// Extracts a `.zip` archive, checking each entry before it's written.
+fn extract_zip(file: File, target: &Path) -> Result<(), BlueprintSourceError> {
+    let mut archive = ZipArchive::new(file).map_err(archive_error)?;
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(archive_error)?;
+        let name = entry.name().to_string();
+        check_entry(&name)?;
+        let path = match (entry.is_symlink(), entry.enclosed_name()) {
+            (false, Some(path)) => target.join(path),
+            _ => return Err(BlueprintSourceError::UnsafeArchiveEntry(name)),
+        };
+
+        if entry.is_dir() {
+            fs::create_dir_all(&path).map_err(archive_error)?;
+            continue;
+        }
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(archive_error)?;
+        }
+        let mut output = File::create(&path).map_err(archive_error)?;
+        io::copy(&mut entry, &mut output).map_err(archive_error)?;
+        if let Some(mode) = entry.unix_mode() {
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode & 0o777))
+                .map_err(archive_error)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Rejects absolute paths and paths that climb out of the target directory.
+fn check_entry(name: &str) -> Result<(), BlueprintSourceError> {
+    let safe = Path::new(name)
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+
+    match safe {
+        true => Ok(()),
+        false => Err(BlueprintSourceError::UnsafeArchiveEntry(name.to_string())),
+    }
+}
+
+fn blueprint_root(dir: &Path) -> Result<PathBuf, BlueprintSourceError> {
+    if dir.join("metadata.yaml").exists() {
+        return Ok(dir.to_path_buf());
+    }
+
+    let entries: Vec<_> = dir
+        .read_dir()
+        .map_err(BlueprintSourceError::LocalReadError)?
+        .collect::<Result<_, _>>()
+        .map_err(BlueprintSourceError::LocalReadError)?;
+
+    match entries.as_slice() {
+        [entry] if entry.path().join("metadata.yaml").exists() => Ok(entry.path()),
+        _ => Err(BlueprintSourceError::ArchiveError(String::from(
+            "no metadata.yaml at the root of the archive or in a single top-level folder",
+        ))),
+    }
+}
+
+fn archive_error(e: impl std::fmt::Display) -> BlueprintSourceError {
+    BlueprintSourceError::ArchiveError(e.to_string())
+}
+
+/// Packs a directory into a `.tar.gz` archive, for tests.
+#[cfg(test)]
+pub fn create_tar_gz(dir: &Path, name: &str, archive: &Path) {
+    let file = File::create(archive).unwrap();
+    let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+        file,
+        flate2::Compression::default(),
+    ));
+    builder.append_dir_all(name, dir).unwrap();
+    builder.into_inner().unwrap().finish().unwrap();
+}
+
+/// Packs the contents of a directory into a `.zip` archive, for tests.
+#[cfg(test)]
+pub fn create_zip(dir: &Path, archive: &Path) {
+    use zip::write::{SimpleFileOptions, ZipWriter};
+
+    let mut writer = ZipWriter::new(File::create(archive).unwrap());
+    for entry in walkdir::WalkDir::new(dir).min_depth(1) {
+        let entry = entry.unwrap();
+        let name = entry.path().strip_prefix(dir).unwrap().to_str().unwrap();
+        if entry.file_type().is_dir() {
+            writer
+                .add_directory(name, SimpleFileOptions::default())
+                .unwrap();
+        } else {
+            writer
+                .start_file(name, SimpleFileOptions::default())
+                .unwrap();
+            io::copy(&mut File::open(entry.path()).unwrap(), &mut writer).unwrap();
+        }
+    }
+    writer.finish().unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn unsafe_entries_are_rejected() {
+        assert!(check_entry("template/README.md").is_ok());
+        assert!(check_entry("./metadata.yaml").is_ok());
+        assert!(check_entry("/etc/passwd").is_err());
+        assert!(check_entry("template/../../evil").is_err());
+    }
+
+    #[test]
+    fn archives_are_extracted_with_a_top_level_folder() {
+        let dir = TempDir::new("blueprint-archive").unwrap();
+        let archive = dir.path().join("blueprint.tgz");
+        create_tar_gz(
+            Path::new("test_assets/example_blueprint"),
+            "example_blueprint",
+            &archive,
+        );
+
+        let target = TempDir::new("extracted").unwrap();
+        let root = extract(&archive, ArchiveKind::TarGz, target.path()).unwrap();
+
+        assert_eq!(root, target.path().join("example_blueprint"));
+        assert!(root.join("template").is_dir());
+    }
+
+    #[test]
+    fn archives_with_links_are_rejected() {
+        let dir = TempDir::new("blueprint-archive").unwrap();
+        let archive = dir.path().join("blueprint.tar.gz");
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            File::create(&archive).unwrap(),
+            flate2::Compression::default(),
+        ));
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(EntryType::Symlink);
+        header.set_size(0);
+        builder
+            .append_link(&mut header, "passwd", "/etc/passwd")
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let target = TempDir::new("extracted").unwrap();
+        match extract(&archive, ArchiveKind::TarGz, target.path()) {
+            Err(BlueprintSourceError::UnsafeArchiveEntry(name)) => assert_eq!(name, "passwd"),
+            _ => panic!("expected an unsafe entry error"),
+        }
+        assert!(!target.path().join("passwd").exists());
+    }
+
+    #[test]
+    fn zip_entries_outside_the_target_are_rejected() {
+        use zip::write::{SimpleFileOptions, ZipWriter};
+
+        let dir = TempDir::new("blueprint-archive").unwrap();
+        let archive = dir.path().join("blueprint.zip");
+        let mut writer = ZipWriter::new(File::create(&archive).unwrap());
+        writer
+            .start_file("../evil", SimpleFileOptions::default())
+            .unwrap();
+        writer.finish().unwrap();
+
+        let target = TempDir::new("extracted").unwrap();
+        match extract(&archive, ArchiveKind::Zip, target.path()) {
+            Err(BlueprintSourceError::UnsafeArchiveEntry(name)) => assert_eq!(name, "../evil"),
+            _ => panic!("expected an unsafe entry error"),
+        }
+    }
+}
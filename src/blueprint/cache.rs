@@ -0,0 +1,359 @@
+//! A persistent cache of remote blueprint repositories. Each repository is
+//! kept as a bare clone under `$XDG_CACHE_HOME/rendr/repos`, in a directory
+//! named after the hash of its URL, and is fetched incrementally on every use.
+//! Downloaded archives are kept under `$XDG_CACHE_HOME/rendr/archives`, in a
+//! directory named after their SHA-256 checksum.
+//!
+//! Fetches over SSH check the server's host key against `known_hosts`, and
+//! fetches over HTTPS can trust an extra CA bundle.
+
+use std::cell::RefCell;
+use std::env;
+use std::ffi::CString;
+use std::fs;
+use std::os::raw::{c_char, c_int};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use git2::{AutotagOption, ErrorCode, FetchOptions, RemoteCallbacks, Repository};
+use log::debug;
+use sha2::{Digest, Sha256};
+use tempdir::TempDir;
+
+use super::known_hosts;
+use super::source::BlueprintSourceError;
+
+/// The name of the file whose modification time records when a cached
+/// repository was last used.
+const LAST_USED_FILE: &str = "rendr-last-used";
+
+/// The name of the file recording the URL a cached archive was downloaded
+/// from.
+const ARCHIVE_URL_FILE: &str = "rendr-url";
+
+pub struct Cache {
+    root: PathBuf,
+    offline: bool,
+    known_hosts: PathBuf,
+    accept_new_host_keys: bool,
+    ca_bundle: Option<PathBuf>,
+}
+
+impl Cache {
+    pub fn new(root: impl Into<PathBuf>, offline: bool) -> Self {
+        Cache {
+            root: root.into(),
+            offline,
+            known_hosts: known_hosts::default_path(),
+            accept_new_host_keys: false,
+            ca_bundle: None,
+        }
+    }
+
+    /// Sets whether the keys of SSH hosts that aren't in `known_hosts` yet
+    /// are trusted and added to it, like OpenSSH's `accept-new`.
+    pub fn accept_new_host_keys(mut self, accept_new: bool) -> Self {
+        self.accept_new_host_keys = accept_new;
+        self
+    }
+
+    /// Sets a file of CA certificates to trust for HTTPS, in addition to the
+    /// system's.
+    pub fn with_ca_bundle(mut self, ca_bundle: Option<PathBuf>) -> Self {
+        self.ca_bundle = ca_bundle;
+        self
+    }
+
+    pub fn ca_bundle(&self) -> Option<&Path> {
+        self.ca_bundle.as_deref()
+    }
+
+    /// The default cache location, which is `~/.cache/rendr` unless
+    /// `XDG_CACHE_HOME` says otherwise.
+    pub fn default_path() -> PathBuf {
+        let cache_dir = match env::var("XDG_CACHE_HOME") {
+            Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => match env::var("HOME") {
+                Ok(home) => PathBuf::from(home).join(".cache"),
+                Err(_) => env::temp_dir(),
+            },
+        };
+
+        cache_dir.join("rendr")
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn offline(&self) -> bool {
+        self.offline
+    }
+
+    fn repo_path(&self, url: &str) -> PathBuf {
+        let hash = Sha256::digest(url.as_bytes());
+
+        self.root.join("repos").join(format!("{:x}", hash))
+    }
+
+    fn archive_dir(&self, sha256: &str) -> PathBuf {
+        self.root.join("archives").join(sha256)
+    }
+
+    /// The cached archive with the checksum, if it has been downloaded before.
+    pub fn archive(
+        &self,
+        sha256: &str,
+        file_name: &str,
+    ) -> Result<Option<PathBuf>, BlueprintSourceError> {
+        let dir = self.archive_dir(sha256);
+        let path = dir.join(file_name);
+        if !path.is_file() {
+            return Ok(None);
+        }
+
+        debug!("Using cached blueprint archive {}", path.display());
+        touch(&dir)?;
+        Ok(Some(path))
+    }
+
+    /// A temporary directory to download an archive into, which can then be
+    /// moved into the cache with `store_archive`.
+    pub fn download_dir(&self) -> Result<TempDir, BlueprintSourceError> {
+        let archives = self.root.join("archives");
+        fs::create_dir_all(&archives).map_err(BlueprintSourceError::CacheError)?;
+
+        TempDir::new_in(archives, ".download").map_err(BlueprintSourceError::CacheError)
+    }
+
+    /// Moves a downloaded archive into the cache, and returns its new path.
+    pub fn store_archive(
+        &self,
+        url: &str,
+        downloaded: &Path,
+        sha256: &str,
+    ) -> Result<PathBuf, BlueprintSourceError> {
+        let dir = self.archive_dir(sha256);
+        debug!("Caching blueprint archive at {}", dir.display());
+        fs::create_dir_all(&dir).map_err(BlueprintSourceError::CacheError)?;
+
+        let path = dir.join(downloaded.file_name().unwrap_or_default());
+        fs::rename(downloaded, &path).map_err(BlueprintSourceError::CacheError)?;
+        fs::write(dir.join(ARCHIVE_URL_FILE), url).map_err(BlueprintSourceError::CacheError)?;
+        touch(&dir)?;
+
+        Ok(path)
+    }
+
+    /// Opens the cached repository for the URL, fetching the latest changes
+    /// into it unless the cache is offline.
+    pub fn fetch(
+        &self,
+        url: &str,
+        callbacks: Option<RemoteCallbacks>,
+    ) -> Result<Repository, BlueprintSourceError> {
+        let path = self.repo_path(url);
+        let cached = path.join("HEAD").exists();
+
+        if self.offline {
+            if !cached {
+                return Err(BlueprintSourceError::NotCached(url.to_string()));
+            }
+            debug!("Using cached blueprint repository {}", path.display());
+            let repo = Repository::open_bare(&path)?;
+            touch(&path)?;
+            return Ok(repo);
+        }
+
+        if let Some(ca_bundle) = &self.ca_bundle {
+            load_ca_bundle(ca_bundle)?;
+        }
+
+        let repo = match cached {
+            true => Repository::open_bare(&path)?,
+            false => {
+                debug!("Caching blueprint repository at {}", path.display());
+                fs::create_dir_all(&path).map_err(BlueprintSourceError::CacheError)?;
+                let repo = Repository::init_bare(&path)?;
+                repo.remote("origin", url)?;
+                repo
+            }
+        };
+
+        // libgit2 doesn't check SSH host keys by itself, so that's done here.
+        // The reason for a rejection is kept, as git only reports that the
+        // check failed.
+        let host_key_error = Rc::new(RefCell::new(None));
+        let mut callbacks = callbacks.unwrap_or_default();
+        if let Some((host, port)) = known_hosts::ssh_host(url) {
+            let known_hosts = self.known_hosts.clone();
+            let accept_new = self.accept_new_host_keys;
+            let host_key_error = Rc::clone(&host_key_error);
+            callbacks.certificate_check(move |cert, _| {
+                let result = match cert.as_hostkey().and_then(|key| key.hash_sha256()) {
+                    Some(hash) => known_hosts::verify(&known_hosts, &host, port, hash, accept_new),
+                    None => Err(BlueprintSourceError::HostKeyError(String::from(
+                        "the server didn't provide a SHA-256 host key fingerprint",
+                    ))),
+                };
+                match result {
+                    Ok(()) => true,
+                    Err(e) => {
+                        host_key_error.borrow_mut().replace(e);
+                        false
+                    }
+                }
+            });
+        }
+
+        let mut fo = FetchOptions::new();
+        fo.remote_callbacks(callbacks);
+        fo.download_tags(AutotagOption::All);
+
+        debug!("Fetching blueprint repository {}", url);
+        let mut remote = repo.find_remote("origin")?;
+        remote
+            .fetch(
+                &[
+                    "+refs/heads/*:refs/remotes/origin/*",
+                    "+refs/tags/*:refs/tags/*",
+                ],
+                Some(&mut fo),
+                None,
+            )
+            .map_err(|e| match (host_key_error.borrow_mut().take(), e.code()) {
+                (Some(host_key_error), _) => host_key_error,
+                (None, ErrorCode::Auth) => BlueprintSourceError::AuthenticationFailed {
+                    url: url.to_string(),
+                    reason: e.message().to_string(),
+                },
+                (None, _) => e.into(),
+            })?;
+
+        // Remember the default branch, so it can be found again offline.
+        if let Ok(branch) = remote.default_branch() {
+            if let Some(branch) = branch.as_str().and_then(|b| b.strip_prefix("refs/heads/")) {
+                repo.reference_symbolic(
+                    "refs/remotes/origin/HEAD",
+                    &format!("refs/remotes/origin/{}", branch),
+                    true,
+                    "rendr: default branch",
+                )?;
+            }
+        }
+        drop(remote);
+
+        touch(&path)?;
+        Ok(repo)
+    }
+
+    /// All repositories and archives in the cache.
+    pub fn entries(&self) -> Result<Vec<CacheEntry>, BlueprintSourceError> {
+        let mut entries = Vec::new();
+        for path in list_dir(&self.root.join("repos"))? {
+            let url = match Repository::open_bare(&path) {
+                Ok(repo) => repo
+                    .find_remote("origin")
+                    .ok()
+                    .and_then(|r| r.url().map(str::to_string)),
+                Err(_) => None,
+            };
+            entries.push(CacheEntry::new(url.unwrap_or_default(), path));
+        }
+        for path in list_dir(&self.root.join("archives"))? {
+            // Downloads that are still in progress don't have a URL yet.
+            if let Ok(url) = fs::read_to_string(path.join(ARCHIVE_URL_FILE)) {
+                entries.push(CacheEntry::new(url, path));
+            }
+        }
+        entries.sort_by(|a, b| a.url.cmp(&b.url));
+
+        Ok(entries)
+    }
+
+    pub fn remove(&self, entry: &CacheEntry) -> Result<(), BlueprintSourceError> {
+        debug!("Removing cached repository {}", entry.path.display());
+
+        fs::remove_dir_all(&entry.path).map_err(BlueprintSourceError::CacheError)
+    }
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Cache::new(Cache::default_path(), false)
+    }
+}
+
+pub struct CacheEntry {
+    pub url: String,
+    pub path: PathBuf,
+    pub last_used: Option<SystemTime>,
+}
+
+impl CacheEntry {
+    fn new(url: String, path: PathBuf) -> Self {
+        let last_used = fs::metadata(path.join(LAST_USED_FILE))
+            .and_then(|m| m.modified())
+            .ok();
+
+        CacheEntry {
+            url,
+            path,
+            last_used,
+        }
+    }
+}
+
+fn list_dir(dir: &Path) -> Result<Vec<PathBuf>, BlueprintSourceError> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    fs::read_dir(dir)
+        .map_err(BlueprintSourceError::CacheError)?
+        .map(|entry| {
+            entry
+                .map(|e| e.path())
+                .map_err(BlueprintSourceError::CacheError)
+        })
+        .collect()
+}
+
+/// Makes libgit2 trust the certificates in the CA bundle for HTTPS.
+fn load_ca_bundle(ca_bundle: &Path) -> Result<(), BlueprintSourceError> {
+    let error = |reason: String| BlueprintSourceError::CaBundleError {
+        path: ca_bundle.to_path_buf(),
+        reason,
+    };
+
+    if !ca_bundle.is_file() {
+        return Err(error(String::from("no such file")));
+    }
+    let file = CString::new(ca_bundle.as_os_str().as_bytes())
+        .map_err(|_| error(String::from("invalid path")))?;
+
+    libgit2_sys::init();
+    // SAFETY: the option takes a file and a directory, either of which may be
+    // null, and only reads them during the call.
+    let result = unsafe {
+        libgit2_sys::git_libgit2_opts(
+            libgit2_sys::GIT_OPT_SET_SSL_CERT_LOCATIONS as c_int,
+            file.as_ptr(),
+            std::ptr::null::<c_char>(),
+        )
+    };
+
+    match result {
+        0 => Ok(()),
+        _ => Err(error(git2::Error::last_error(result).map_or_else(
+            || String::from("unknown error"),
+            |e| e.message().to_string(),
+        ))),
+    }
+}
+
+fn touch(dir: &Path) -> Result<(), BlueprintSourceError> {
+    fs::write(dir.join(LAST_USED_FILE), "").map_err(BlueprintSourceError::CacheError)
+}
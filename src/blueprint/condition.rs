@@ -0,0 +1,210 @@
+//! Conditions on values, like `use_docker == true`, which decide whether a
+//! blueprint's files are rendered.
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+use thiserror::Error;
+
+use super::{Value, Values};
+
+/// A condition on values. It's any number of comparisons joined by `&&` and
+/// `||`, where `&&` binds tighter. A comparison is one of:
+///
+/// - `name == value` or `name != value`, with the value optionally quoted
+/// - `name`, which holds if the value is set and isn't empty or `false`
+/// - `!name`, which holds otherwise
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    source: String,
+    any: Vec<Vec<Comparison>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Comparison {
+    Equals {
+        name: String,
+        value: String,
+        negated: bool,
+    },
+    IsSet {
+        name: String,
+        negated: bool,
+    },
+}
+
+impl Condition {
+    pub fn evaluate(&self, values: &Values) -> bool {
+        self.any
+            .iter()
+            .any(|all| all.iter().all(|comparison| comparison.evaluate(values)))
+    }
+}
+
+impl Comparison {
+    fn evaluate(&self, values: &Values) -> bool {
+        match self {
+            Comparison::Equals {
+                name,
+                value,
+                negated,
+            } => (values.get(name) == Some(value)) != *negated,
+            Comparison::IsSet { name, negated } => {
+                let set = match values.get_value(name) {
+                    Some(Value::String(s)) => !s.is_empty() && s != "false",
+                    Some(Value::List(list)) => !list.is_empty(),
+                    Some(Value::Map(map)) => !map.is_empty(),
+                    None => false,
+                };
+                set != *negated
+            }
+        }
+    }
+}
+
+impl FromStr for Comparison {
+    type Err = ConditionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ConditionError::InvalidComparison(s.trim().to_string());
+
+        let (name, value, negated) = match s.split_once("!=") {
+            Some((name, value)) => (name, Some(value), true),
+            None => match s.split_once("==") {
+                Some((name, value)) => (name, Some(value), false),
+                None => match s.trim().strip_prefix('!') {
+                    Some(name) => (name, None, true),
+                    None => (s, None, false),
+                },
+            },
+        };
+
+        let name = name.trim();
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_alphanumeric() || "_.-".contains(c))
+        {
+            return Err(invalid());
+        }
+
+        let value = match value.map(str::trim) {
+            None => {
+                return Ok(Comparison::IsSet {
+                    name: name.to_string(),
+                    negated,
+                })
+            }
+            Some("") => return Err(invalid()),
+            Some(value) => value,
+        };
+        let unquoted = ['"', '\'']
+            .iter()
+            .find_map(|q| value.strip_prefix(*q)?.strip_suffix(*q))
+            .unwrap_or(value);
+
+        Ok(Comparison::Equals {
+            name: name.to_string(),
+            value: unquoted.to_string(),
+            negated,
+        })
+    }
+}
+
+impl FromStr for Condition {
+    type Err = ConditionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let any = s
+            .split("||")
+            .map(|all| all.split("&&").map(str::parse).collect())
+            .collect::<Result<_, _>>()?;
+
+        Ok(Condition {
+            source: s.trim().to_string(),
+            any,
+        })
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl Serialize for Condition {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Condition {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ConditionError {
+    #[error("invalid condition `{0}`, expected something like `name == value`")]
+    InvalidComparison(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values() -> Values {
+        let mut values = Values::new();
+        values.insert("use_docker", "true");
+        values.insert("build_tool", "gradle");
+        values.insert("extra", "");
+        values.insert("database.name", "orders");
+        values
+    }
+
+    fn holds(condition: &str) -> bool {
+        condition.parse::<Condition>().unwrap().evaluate(&values())
+    }
+
+    #[test]
+    fn conditions_compare_values() {
+        assert!(holds("use_docker == true"));
+        assert!(holds("build_tool == 'gradle'"));
+        assert!(holds("build_tool != maven"));
+        assert!(holds("database.name == \"orders\""));
+        assert!(!holds("build_tool == maven"));
+        assert!(!holds("missing == true"));
+        assert!(holds("missing != true"));
+
+        assert!(holds("use_docker"));
+        assert!(holds("database"));
+        assert!(!holds("extra"));
+        assert!(!holds("missing"));
+        assert!(holds("!missing"));
+
+        assert!(holds("build_tool == maven || use_docker"));
+        assert!(!holds("build_tool == gradle && missing"));
+        assert!(holds("missing && extra || build_tool == gradle"));
+    }
+
+    #[test]
+    fn invalid_conditions_are_rejected() {
+        assert!("".parse::<Condition>().is_err());
+        assert!("use_docker ==".parse::<Condition>().is_err());
+        assert!("== true".parse::<Condition>().is_err());
+        assert!("use docker".parse::<Condition>().is_err());
+        assert!("a && || b".parse::<Condition>().is_err());
+        assert_eq!(
+            serde_yaml::from_str::<Condition>("use_docker == true")
+                .unwrap()
+                .to_string(),
+            "use_docker == true"
+        );
+    }
+}
@@ -0,0 +1,344 @@
+//! Credentials for git sources. Each time git asks for credentials, the next
+//! candidate is tried: for SSH, a provided key, the ssh-agent and then the
+//! default keys in `~/.ssh`; for HTTPS, a provided password (or `GIT_PASS`),
+//! a token from `RENDR_GIT_TOKEN`, git's credential helpers, `~/.netrc` and
+//! finally an interactive prompt.
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use git2::{Cred, CredentialType, ErrorClass, ErrorCode};
+use log::debug;
+use text_io::read;
+
+use super::BlueprintAuth;
+
+/// The environment variable holding the password for git authentication.
+pub const PASSWORD_ENV_VAR: &str = "GIT_PASS";
+
+/// The environment variable holding an access token for git authentication.
+pub const TOKEN_ENV_VAR: &str = "RENDR_GIT_TOKEN";
+
+/// The default SSH keys, in the order they're tried.
+const DEFAULT_SSH_KEYS: &[&str] = &["id_ed25519", "id_ecdsa", "id_rsa"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SshCandidate {
+    ProvidedKey,
+    Agent,
+    DefaultKey(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PasswordCandidate {
+    Provided,
+    Token,
+    CredentialHelper,
+    Netrc,
+    Prompt,
+}
+
+const PASSWORD_CANDIDATES: &[PasswordCandidate] = &[
+    PasswordCandidate::Provided,
+    PasswordCandidate::Token,
+    PasswordCandidate::CredentialHelper,
+    PasswordCandidate::Netrc,
+    PasswordCandidate::Prompt,
+];
+
+pub struct Credentials {
+    user: Option<String>,
+    password: Option<String>,
+    ssh_key: Option<String>,
+    ssh_attempts: usize,
+    password_attempts: usize,
+}
+
+impl Credentials {
+    pub fn new(auth: Option<BlueprintAuth>) -> Self {
+        let auth = auth.unwrap_or_else(|| BlueprintAuth::new(None, None, None));
+
+        Credentials {
+            user: auth.user,
+            password: auth
+                .password
+                .or_else(|| env::var(PASSWORD_ENV_VAR).ok().filter(|p| !p.is_empty())),
+            ssh_key: auth.ssh_key,
+            ssh_attempts: 0,
+            password_attempts: 0,
+        }
+    }
+
+    /// The next credentials to try for the URL. Once all candidates have been
+    /// tried, an authentication error is returned.
+    pub fn next(
+        &mut self,
+        url: &str,
+        username_from_url: Option<&str>,
+        allowed_types: CredentialType,
+    ) -> Result<Cred, git2::Error> {
+        debug!("Git requested cred types: {:?}", allowed_types);
+
+        if allowed_types.is_username() {
+            return Cred::username(&self.username(username_from_url)?);
+        }
+
+        if allowed_types.is_ssh_key() {
+            while let Some(candidate) = self.next_ssh_candidate() {
+                if let Some(cred) = self.ssh_cred(candidate, username_from_url)? {
+                    return Ok(cred);
+                }
+            }
+        }
+
+        if allowed_types.is_user_pass_plaintext() {
+            while let Some(candidate) = PASSWORD_CANDIDATES.get(self.password_attempts).copied() {
+                self.password_attempts += 1;
+                if let Some(cred) = self.password_cred(candidate, url, username_from_url)? {
+                    return Ok(cred);
+                }
+            }
+        }
+
+        Err(auth_error(format!(
+            "no more credentials to try (requested: {:?})",
+            allowed_types
+        )))
+    }
+
+    fn next_ssh_candidate(&mut self) -> Option<SshCandidate> {
+        let candidate = match self.ssh_attempts {
+            0 => SshCandidate::ProvidedKey,
+            1 => SshCandidate::Agent,
+            n if n - 2 < DEFAULT_SSH_KEYS.len() => SshCandidate::DefaultKey(n - 2),
+            _ => return None,
+        };
+        self.ssh_attempts += 1;
+
+        Some(candidate)
+    }
+
+    fn ssh_cred(
+        &self,
+        candidate: SshCandidate,
+        username_from_url: Option<&str>,
+    ) -> Result<Option<Cred>, git2::Error> {
+        let user = self.username(username_from_url)?;
+
+        match candidate {
+            SshCandidate::ProvidedKey => match &self.ssh_key {
+                Some(key) => {
+                    debug!("Trying SSH key {}", key);
+                    Cred::ssh_key(&user, None, Path::new(key), None).map(Some)
+                }
+                None => Ok(None),
+            },
+            SshCandidate::Agent => match env::var_os("SSH_AUTH_SOCK") {
+                Some(_) => {
+                    debug!("Trying the ssh-agent");
+                    Cred::ssh_key_from_agent(&user).map(Some)
+                }
+                None => Ok(None),
+            },
+            // A provided key replaces the default ones.
+            SshCandidate::DefaultKey(_) if self.ssh_key.is_some() => Ok(None),
+            SshCandidate::DefaultKey(i) => match default_ssh_key(DEFAULT_SSH_KEYS[i]) {
+                Some(key) => {
+                    debug!("Trying SSH key {}", key.display());
+                    Cred::ssh_key(&user, None, &key, None).map(Some)
+                }
+                None => Ok(None),
+            },
+        }
+    }
+
+    fn password_cred(
+        &self,
+        candidate: PasswordCandidate,
+        url: &str,
+        username_from_url: Option<&str>,
+    ) -> Result<Option<Cred>, git2::Error> {
+        match candidate {
+            PasswordCandidate::Provided => match &self.password {
+                Some(password) => {
+                    let user = self.username(username_from_url)?;
+                    Cred::userpass_plaintext(&user, password).map(Some)
+                }
+                None => Ok(None),
+            },
+            PasswordCandidate::Token => match env::var(TOKEN_ENV_VAR) {
+                Ok(token) if !token.is_empty() => {
+                    debug!("Trying the token from {}", TOKEN_ENV_VAR);
+                    let user = self
+                        .user
+                        .as_deref()
+                        .or(username_from_url)
+                        .unwrap_or("x-access-token");
+                    Cred::userpass_plaintext(user, &token).map(Some)
+                }
+                _ => Ok(None),
+            },
+            PasswordCandidate::CredentialHelper => {
+                let config = git2::Config::open_default()?;
+                let user = self.user.as_deref().or(username_from_url);
+                match Cred::credential_helper(&config, url, user) {
+                    Ok(cred) => {
+                        debug!("Trying git's credential helper");
+                        Ok(Some(cred))
+                    }
+                    Err(_) => Ok(None),
+                }
+            }
+            PasswordCandidate::Netrc => match netrc_path()
+                .and_then(|path| fs::read_to_string(path).ok())
+                .and_then(|netrc| netrc_lookup(&netrc, host(url)))
+            {
+                Some((login, password)) => {
+                    debug!("Trying credentials from .netrc");
+                    Cred::userpass_plaintext(&login, &password).map(Some)
+                }
+                None => Ok(None),
+            },
+            PasswordCandidate::Prompt => {
+                let user = self.username(username_from_url)?;
+                let password = rpassword::read_password_from_tty(Some("Password: "))
+                    .map_err(|e| auth_error(format!("failed to read password: {}", e)))?;
+                Cred::userpass_plaintext(&user, &password).map(Some)
+            }
+        }
+    }
+
+    fn username(&self, username_from_url: Option<&str>) -> Result<String, git2::Error> {
+        if let Some(username) = self.user.as_deref().or(username_from_url) {
+            return Ok(username.to_string());
+        }
+
+        print!("Username: ");
+        io::stdout()
+            .flush()
+            .map_err(|e| auth_error(format!("failed to prompt for username: {}", e)))?;
+        Ok(read!("{}\n"))
+    }
+}
+
+fn auth_error(message: String) -> git2::Error {
+    git2::Error::new(ErrorCode::Auth, ErrorClass::Callback, message)
+}
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME").map(PathBuf::from)
+}
+
+fn default_ssh_key(name: &str) -> Option<PathBuf> {
+    let key = home_dir()?.join(".ssh").join(name);
+
+    Some(key).filter(|k| k.exists())
+}
+
+fn netrc_path() -> Option<PathBuf> {
+    match env::var_os("NETRC") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => Some(home_dir()?.join(".netrc")),
+    }
+}
+
+/// The host part of a git URL, like `github.com` for both
+/// `https://user@github.com/org/repo` and `git@github.com:org/repo`.
+fn host(url: &str) -> &str {
+    let rest = url.splitn(2, "://").last().unwrap_or(url);
+    let rest = rest.rsplit_once('@').map_or(rest, |(_, r)| r);
+
+    rest.split(['/', ':']).next().unwrap_or(rest)
+}
+
+/// The login and password for a host in a `.netrc` file, falling back to the
+/// `default` entry.
+fn netrc_lookup(netrc: &str, host: &str) -> Option<(String, String)> {
+    let mut tokens = netrc.split_whitespace();
+    let mut entries = Vec::new();
+
+    while let Some(token) = tokens.next() {
+        match token {
+            "machine" => entries.push((tokens.next().map(str::to_string), None, None)),
+            "default" => entries.push((None, None, None)),
+            "login" => {
+                if let Some(entry) = entries.last_mut() {
+                    entry.1 = tokens.next().map(str::to_string);
+                }
+            }
+            "password" => {
+                if let Some(entry) = entries.last_mut() {
+                    entry.2 = tokens.next().map(str::to_string);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let entry = entries
+        .iter()
+        .find(|(machine, _, _)| machine.as_deref() == Some(host))
+        .or_else(|| entries.iter().find(|(machine, _, _)| machine.is_none()))?;
+
+    match entry {
+        (_, Some(login), Some(password)) => Some((login.clone(), password.clone())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hosts_are_found_in_git_urls() {
+        assert_eq!(host("https://github.com/org/repo"), "github.com");
+        assert_eq!(
+            host("https://user@gitlab.example.com:8443/repo"),
+            "gitlab.example.com"
+        );
+        assert_eq!(host("git@github.com:org/repo.git"), "github.com");
+        assert_eq!(host("ssh://git@github.com/org/repo.git"), "github.com");
+    }
+
+    #[test]
+    fn netrc_entries_are_looked_up_by_host() {
+        let netrc = "machine github.com login octocat password hunter2\n\
+                     machine example.com\n  login someone\n  password secret\n\
+                     default login anonymous password guest\n";
+
+        assert_eq!(
+            netrc_lookup(netrc, "github.com"),
+            Some((String::from("octocat"), String::from("hunter2")))
+        );
+        assert_eq!(
+            netrc_lookup(netrc, "example.com"),
+            Some((String::from("someone"), String::from("secret")))
+        );
+        assert_eq!(
+            netrc_lookup(netrc, "gitlab.com"),
+            Some((String::from("anonymous"), String::from("guest")))
+        );
+        assert_eq!(netrc_lookup("machine a.com login x", "a.com"), None);
+    }
+
+    #[test]
+    fn running_out_of_credentials_is_an_auth_error() {
+        let mut credentials = Credentials::new(None);
+        credentials.ssh_attempts = usize::MAX;
+        credentials.password_attempts = PASSWORD_CANDIDATES.len();
+
+        let error = credentials
+            .next(
+                "https://example.com/repo",
+                Some("git"),
+                CredentialType::USER_PASS_PLAINTEXT,
+            )
+            .err()
+            .unwrap();
+
+        assert_eq!(error.code(), ErrorCode::Auth);
+    }
+}
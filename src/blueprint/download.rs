@@ -0,0 +1,81 @@
+//! Downloading of blueprint archives over HTTP(S), optionally sending a bearer
+//! token, which is taken from the `RENDR_HTTP_TOKEN` environment variable.
+
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use log::debug;
+use native_tls::{Certificate, TlsConnector};
+use sha2::{Digest, Sha256};
+
+use super::source::BlueprintSourceError;
+
+/// The environment variable holding a bearer token for blueprint downloads.
+pub const HTTP_TOKEN_ENV_VAR: &str = "RENDR_HTTP_TOKEN";
+
+/// Whether the source is an HTTP(S) URL.
+pub fn is_http(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+/// The bearer token for blueprint downloads, if one is set.
+pub fn token_from_env() -> Option<String> {
+    env::var(HTTP_TOKEN_ENV_VAR).ok().filter(|t| !t.is_empty())
+}
+
+/// Downloads the URL into the target file. The certificates in the CA bundle,
+/// if one is given, are trusted in addition to the system's.
+pub fn download(
+    url: &str,
+    target: &Path,
+    ca_bundle: Option<&Path>,
+    token: Option<&str>,
+) -> Result<(), BlueprintSourceError> {
+    debug!("Downloading {} to {}", url, target.display());
+
+    let mut tls = TlsConnector::builder();
+    if let Some(ca_bundle) = ca_bundle {
+        for certificate in load_ca_bundle(ca_bundle)? {
+            tls.add_root_certificate(certificate);
+        }
+    }
+    let tls = tls.build().map_err(download_error)?;
+    let agent = ureq::AgentBuilder::new()
+        .tls_connector(Arc::new(tls))
+        .build();
+
+    let mut request = agent.get(url);
+    if let Some(token) = token {
+        request = request.set("Authorization", &format!("Bearer {}", token));
+    }
+    let response = request.call().map_err(download_error)?;
+
+    let mut file = File::create(target).map_err(download_error)?;
+    io::copy(&mut response.into_reader(), &mut file).map_err(download_error)?;
+
+    Ok(())
+}
+
+/// The hex-encoded SHA-256 checksum of a file.
+pub fn sha256(path: &Path) -> Result<String, BlueprintSourceError> {
+    let contents = fs::read(path).map_err(BlueprintSourceError::LocalReadError)?;
+
+    Ok(format!("{:x}", Sha256::digest(&contents)))
+}
+
+fn load_ca_bundle(ca_bundle: &Path) -> Result<Vec<Certificate>, BlueprintSourceError> {
+    let error = |reason: String| BlueprintSourceError::CaBundleError {
+        path: ca_bundle.to_path_buf(),
+        reason,
+    };
+
+    let pem = fs::read(ca_bundle).map_err(|e| error(e.to_string()))?;
+    Certificate::stack_from_pem(&pem).map_err(|e| error(e.to_string()))
+}
+
+fn download_error(e: impl std::fmt::Display) -> BlueprintSourceError {
+    BlueprintSourceError::DownloadError(e.to_string())
+}
@@ -0,0 +1,231 @@
+//! Encryption of values stored in a project's `.rendr.yaml` file. Values are
+//! encrypted with an [age](https://age-encryption.org) X25519 identity that
+//! only ever lives on the local machine.
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use age::secrecy::ExposeSecret;
+use age::x25519::Identity;
+use log::debug;
+use thiserror::Error;
+
+pub struct LocalKey {
+    identity: Identity,
+}
+
+impl LocalKey {
+    /// The default location of the identity file, which is
+    /// `~/.config/rendr/keys/identity.txt` unless `XDG_CONFIG_HOME` says otherwise.
+    pub fn default_path() -> Result<PathBuf, EncryptionError> {
+        let config_dir = match env::var("XDG_CONFIG_HOME") {
+            Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var("HOME").map_err(|_| EncryptionError::NoHomeDir)?)
+                .join(".config"),
+        };
+
+        Ok(config_dir.join("rendr").join("keys").join("identity.txt"))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, EncryptionError> {
+        let path = path.as_ref();
+        debug!("Loading encryption key from {}", path.display());
+
+        if !path.exists() {
+            return Err(EncryptionError::KeyNotFound(path.to_path_buf()));
+        }
+
+        let contents = fs::read_to_string(path).map_err(EncryptionError::KeyReadError)?;
+
+        // Identity files may contain comments, like the ones we write below.
+        let line = contents
+            .lines()
+            .map(str::trim)
+            .find(|l| !l.is_empty() && !l.starts_with('#'))
+            .ok_or_else(|| EncryptionError::KeyParseError(path.to_path_buf(), "empty file"))?;
+        let identity = Identity::from_str(line)
+            .map_err(|e| EncryptionError::KeyParseError(path.to_path_buf(), e))?;
+
+        Ok(LocalKey { identity })
+    }
+
+    /// Loads the key at the given path, generating a new one if there is none yet.
+    pub fn load_or_generate(path: impl AsRef<Path>) -> Result<Self, EncryptionError> {
+        let path = path.as_ref();
+
+        if path.exists() {
+            return Self::load(path);
+        }
+
+        debug!("Generating a new encryption key at {}", path.display());
+        let identity = Identity::generate();
+        let contents = format!(
+            "# public key: {}\n{}\n",
+            identity.to_public(),
+            identity.to_string().expose_secret()
+        );
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(EncryptionError::KeyWriteError)?;
+        }
+        // The file is only ever readable by the user, even while it's written.
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path)
+            .and_then(|mut file| file.write_all(contents.as_bytes()))
+            .map_err(EncryptionError::KeyWriteError)?;
+
+        Ok(LocalKey { identity })
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> Result<String, EncryptionError> {
+        Ok(age::encrypt_and_armor(
+            &self.identity.to_public(),
+            plaintext.as_bytes(),
+        )?)
+    }
+
+    pub fn decrypt(&self, ciphertext: &str) -> Result<String, EncryptionError> {
+        let plaintext = age::decrypt(&self.identity, ciphertext.as_bytes())?;
+
+        String::from_utf8(plaintext).map_err(|_| EncryptionError::InvalidPlaintext)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum EncryptionError {
+    #[error("couldn't determine the home directory")]
+    NoHomeDir,
+
+    #[error("no encryption key found at {0}")]
+    KeyNotFound(PathBuf),
+
+    #[error("failed to read the encryption key")]
+    KeyReadError(#[source] std::io::Error),
+
+    #[error("failed to parse the encryption key at {0}: {1}")]
+    KeyParseError(PathBuf, &'static str),
+
+    #[error("failed to write the encryption key")]
+    KeyWriteError(#[source] std::io::Error),
+
+    #[error("failed to encrypt a value")]
+    EncryptError(#[from] age::EncryptError),
+
+    #[error("failed to decrypt a value; was it encrypted with a different key?")]
+    DecryptError(#[from] age::DecryptError),
+
+    #[error("the decrypted value is not valid UTF-8")]
+    InvalidPlaintext,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempdir::TempDir;
+
+    #[test]
+    fn generated_key_can_be_loaded_again() {
+        let dir = TempDir::new("rendr-keys").unwrap();
+        let path = dir.path().join("keys").join("identity.txt");
+
+        let key = LocalKey::load_or_generate(&path).unwrap();
+        let ciphertext = key.encrypt("hunter2").unwrap();
+
+        assert!(ciphertext.find("hunter2").is_none());
+
+        let key = LocalKey::load(&path).unwrap();
+
+        assert_eq!(key.decrypt(&ciphertext).unwrap(), "hunter2");
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn encrypted_values_round_trip_through_yaml() {
+        use crate::blueprint::{StoredValue, StoredValues, Value, Values};
+        use std::collections::HashMap;
+
+        let dir = TempDir::new("rendr-keys").unwrap();
+        let key = LocalKey::load_or_generate(dir.path().join("identity.txt")).unwrap();
+
+        let values: Values = vec![("name", "my-project"), ("api_token", "hunter2")]
+            .into_iter()
+            .collect::<HashMap<_, _>>()
+            .into();
+        let mut stored = StoredValues::from(&values);
+        stored.encrypt("api_token", None, &key).unwrap();
+
+        let yaml = serde_yaml::to_string(&stored).unwrap();
+        assert!(yaml.find("hunter2").is_none());
+
+        let parsed: StoredValues = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(
+            parsed.get("name"),
+            Some(&StoredValue::Plain(Value::from("my-project")))
+        );
+        assert!(parsed.is_encrypted("api_token"));
+
+        // Unchanged values keep their ciphertext when re-encrypted.
+        let mut regenerated = StoredValues::from(&values);
+        regenerated.encrypt_like(&parsed, &key).unwrap();
+        assert_eq!(regenerated.get("api_token"), parsed.get("api_token"));
+
+        let decrypted = regenerated.decrypted(Some(&key)).unwrap();
+        assert_eq!(decrypted.get("api_token").unwrap(), "hunter2");
+    }
+
+    #[test]
+    fn nested_values_are_encrypted_by_their_path() {
+        use crate::blueprint::{StoredValues, Values};
+
+        let dir = TempDir::new("rendr-keys").unwrap();
+        let key = LocalKey::load_or_generate(dir.path().join("identity.txt")).unwrap();
+
+        let mut values = Values::new();
+        values.insert("database.host", "localhost");
+        values.insert("database.password", "hunter2");
+        let mut stored = StoredValues::from(&values);
+        assert!(stored.contains("database.password"));
+        assert!(!stored.contains("database.user"));
+
+        stored.encrypt("database.password", None, &key).unwrap();
+        assert!(stored.is_encrypted("database.password"));
+        let yaml = serde_yaml::to_string(&stored).unwrap();
+        assert!(yaml.find("hunter2").is_none());
+        assert!(yaml.find("localhost").is_some());
+
+        let decrypted = stored.decrypted(Some(&key)).unwrap();
+        assert_eq!(decrypted.get("database.host").unwrap(), "localhost");
+        assert_eq!(decrypted.get("database.password").unwrap(), "hunter2");
+
+        stored.decrypt("database.password", &key).unwrap();
+        assert!(stored.encrypted_names().is_empty());
+        assert_eq!(
+            stored
+                .decrypted(None)
+                .unwrap()
+                .get("database.password")
+                .unwrap(),
+            "hunter2"
+        );
+    }
+
+    #[test]
+    fn loading_a_missing_key_fails() {
+        let dir = TempDir::new("rendr-keys").unwrap();
+
+        match LocalKey::load(dir.path().join("identity.txt")) {
+            Err(EncryptionError::KeyNotFound(_)) => {}
+            _ => panic!("expected a missing key error"),
+        }
+    }
+}
@@ -0,0 +1,392 @@
+//! Verification of SSH host keys against `known_hosts` files, following
+//! OpenSSH's format: hashed host names, wildcards, negated patterns,
+//! non-standard ports and `@revoked` markers are all understood. Unknown
+//! hosts are rejected, unless new host keys are accepted, in which case the
+//! key is fetched with `ssh-keyscan` and added to the user's `known_hosts`.
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use log::{debug, info};
+use openssl::base64;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sha::sha256;
+use openssl::sign::Signer;
+
+use super::source::BlueprintSourceError;
+
+/// The system-wide `known_hosts` file, which is only ever read.
+const GLOBAL_KNOWN_HOSTS: &str = "/etc/ssh/ssh_known_hosts";
+
+const DEFAULT_SSH_PORT: u16 = 22;
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+enum HostKeyStatus {
+    Unknown,
+    Mismatch,
+    Known,
+    Revoked,
+}
+
+/// The user's `known_hosts` file, `~/.ssh/known_hosts`.
+pub fn default_path() -> PathBuf {
+    let home = env::var_os("HOME").map_or_else(env::temp_dir, PathBuf::from);
+
+    home.join(".ssh").join("known_hosts")
+}
+
+/// The host and port of an SSH git URL, either `ssh://[user@]host[:port]/path`
+/// or the scp-like `[user@]host:path`. Other URLs give `None`.
+pub fn ssh_host(url: &str) -> Option<(String, u16)> {
+    let (authority, scp_like) = match url.split_once("://") {
+        Some((scheme, rest)) => match scheme {
+            "ssh" | "ssh+git" | "git+ssh" => (rest.split('/').next()?, false),
+            _ => return None,
+        },
+        None => {
+            let (authority, _) = url.split_once(':')?;
+            if authority.contains('/') {
+                return None;
+            }
+            (authority, true)
+        }
+    };
+    let authority = authority.rsplit_once('@').map_or(authority, |(_, a)| a);
+
+    let (host, port) = match authority.strip_prefix('[') {
+        Some(rest) => {
+            let (host, rest) = rest.split_once(']')?;
+            (host, rest.strip_prefix(':'))
+        }
+        None if scp_like => (authority, None),
+        None => match authority.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        },
+    };
+    let port = match port {
+        Some(port) => port.parse().ok()?,
+        None => DEFAULT_SSH_PORT,
+    };
+
+    Some((host.to_string(), port))
+}
+
+/// Checks the host key with the given SHA-256 hash against the `known_hosts`
+/// file and the system-wide one. If the host isn't known yet and `accept_new`
+/// is set, its key is added to the `known_hosts` file.
+pub fn verify(
+    known_hosts: &Path,
+    host: &str,
+    port: u16,
+    key_sha256: &[u8; 32],
+    accept_new: bool,
+) -> Result<(), BlueprintSourceError> {
+    let name = host_name(host, port);
+    let fingerprint = fingerprint(key_sha256);
+    debug!("Verifying host key {} for {}", fingerprint, name);
+
+    let status = [known_hosts, Path::new(GLOBAL_KNOWN_HOSTS)]
+        .iter()
+        .filter_map(|file| fs::read_to_string(file).ok())
+        .map(|contents| lookup(&contents, &name, key_sha256))
+        .fold(HostKeyStatus::Unknown, |a, b| if b > a { b } else { a });
+
+    match status {
+        HostKeyStatus::Known => Ok(()),
+        HostKeyStatus::Revoked => Err(BlueprintSourceError::RevokedHostKey {
+            host: name,
+            fingerprint,
+        }),
+        HostKeyStatus::Mismatch => Err(BlueprintSourceError::HostKeyMismatch {
+            host: name,
+            fingerprint,
+        }),
+        HostKeyStatus::Unknown if accept_new => {
+            add(known_hosts, host, port, key_sha256)?;
+            info!(
+                "Permanently added the host key {} for {} to {}",
+                fingerprint,
+                name,
+                known_hosts.display()
+            );
+            Ok(())
+        }
+        HostKeyStatus::Unknown => Err(BlueprintSourceError::UnknownHostKey {
+            host: name,
+            fingerprint,
+        }),
+    }
+}
+
+/// The status of a host key according to the contents of a `known_hosts`
+/// file. A matching revoked key wins over everything else, and a matching key
+/// over other keys for the same host.
+fn lookup(contents: &str, name: &str, key_sha256: &[u8; 32]) -> HostKeyStatus {
+    let mut status = HostKeyStatus::Unknown;
+
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut fields = line.split_whitespace();
+        let (revoked, patterns) = match fields.next() {
+            Some("@revoked") => (true, fields.next()),
+            // Certificate authorities can't be checked with just a key hash.
+            Some("@cert-authority") => continue,
+            patterns => (false, patterns),
+        };
+        let key = match (patterns, fields.next(), fields.next()) {
+            (Some(patterns), Some(_key_type), Some(key)) if host_matches(patterns, name) => key,
+            _ => continue,
+        };
+        let matches = base64::decode_block(key)
+            .map(|key| &sha256(&key) == key_sha256)
+            .unwrap_or(false);
+
+        let line_status = match (revoked, matches) {
+            (true, true) => HostKeyStatus::Revoked,
+            (true, false) => continue,
+            (false, true) => HostKeyStatus::Known,
+            (false, false) => HostKeyStatus::Mismatch,
+        };
+        if line_status > status {
+            status = line_status;
+        }
+    }
+
+    status
+}
+
+/// Whether a comma-separated list of host patterns, or a hashed host name,
+/// matches the name.
+fn host_matches(patterns: &str, name: &str) -> bool {
+    if let Some(hashed) = patterns.strip_prefix("|1|") {
+        return hashed_host_matches(hashed, name);
+    }
+
+    let mut matched = false;
+    for pattern in patterns.split(',') {
+        match pattern.strip_prefix('!') {
+            Some(negated) if wildcard_matches(negated, name) => return false,
+            Some(_) => {}
+            None => matched = matched || wildcard_matches(pattern, name),
+        }
+    }
+
+    matched
+}
+
+/// Checks a `salt|hash` pair, where the hash is the HMAC-SHA1 of the host
+/// name keyed with the salt, both base64-encoded.
+fn hashed_host_matches(hashed: &str, name: &str) -> bool {
+    let (salt, hash) = match hashed.split_once('|') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    let (salt, hash) = match (base64::decode_block(salt), base64::decode_block(hash)) {
+        (Ok(salt), Ok(hash)) => (salt, hash),
+        _ => return false,
+    };
+
+    let hmac = PKey::hmac(&salt)
+        .and_then(|key| {
+            let mut signer = Signer::new(MessageDigest::sha1(), &key)?;
+            signer.update(name.as_bytes())?;
+            signer.sign_to_vec()
+        })
+        .ok();
+
+    hmac == Some(hash)
+}
+
+/// Matches `*` and `?` wildcards, case-insensitively like OpenSSH.
+fn wildcard_matches(pattern: &str, name: &str) -> bool {
+    fn matches(pattern: &[u8], name: &[u8]) -> bool {
+        match (pattern.split_first(), name.split_first()) {
+            (None, None) => true,
+            (Some((b'*', rest)), _) => {
+                matches(rest, name) || (!name.is_empty() && matches(pattern, &name[1..]))
+            }
+            (Some((b'?', rest)), Some((_, name))) => matches(rest, name),
+            (Some((p, rest)), Some((n, name))) => p.eq_ignore_ascii_case(n) && matches(rest, name),
+            _ => false,
+        }
+    }
+
+    matches(pattern.as_bytes(), name.as_bytes())
+}
+
+/// The name a host is recorded under in `known_hosts`.
+fn host_name(host: &str, port: u16) -> String {
+    match port {
+        DEFAULT_SSH_PORT => host.to_string(),
+        port => format!("[{}]:{}", host, port),
+    }
+}
+
+/// The fingerprint of a host key as OpenSSH shows it.
+fn fingerprint(key_sha256: &[u8; 32]) -> String {
+    let encoded = base64::encode_block(key_sha256);
+
+    format!("SHA256:{}", encoded.trim_end_matches('='))
+}
+
+/// Fetches the host's keys with `ssh-keyscan`, and adds the one that was
+/// presented during the connection to the `known_hosts` file.
+fn add(
+    known_hosts: &Path,
+    host: &str,
+    port: u16,
+    key_sha256: &[u8; 32],
+) -> Result<(), BlueprintSourceError> {
+    let output = Command::new("ssh-keyscan")
+        .args(["-T", "10", "-p", &port.to_string(), host])
+        .output()
+        .map_err(|e| error(format!("failed to run ssh-keyscan: {}", e)))?;
+    let scanned = String::from_utf8_lossy(&output.stdout);
+
+    let (key_type, key) = scanned
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace().skip(1);
+            Some((fields.next()?, fields.next()?))
+        })
+        .find(|(_, key)| {
+            base64::decode_block(key)
+                .map(|key| &sha256(&key) == key_sha256)
+                .unwrap_or(false)
+        })
+        .ok_or_else(|| {
+            error(format!(
+                "ssh-keyscan didn't return the key {} presented by {}",
+                fingerprint(key_sha256),
+                host_name(host, port)
+            ))
+        })?;
+
+    if let Some(dir) = known_hosts.parent() {
+        fs::create_dir_all(dir).map_err(error)?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(known_hosts)
+        .map_err(error)?;
+    writeln!(file, "{} {} {}", host_name(host, port), key_type, key).map_err(error)
+}
+
+fn error(e: impl ToString) -> BlueprintSourceError {
+    BlueprintSourceError::HostKeyError(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    const KEY: &str = "AAAAC3NzaC1lZDI1NTE5AAAAIJQ6XRKWzhgWToMNaLbrYgUT9cL25/ODcyv8KOb7tfhx";
+    const OTHER_KEY: &str = "AAAAC3NzaC1lZDI1NTE5AAAAIGSjbF4+Pc4FXl29ai86sIokUMC66/mKSfKyiDAISyJv";
+
+    fn key_sha256(key: &str) -> [u8; 32] {
+        sha256(&base64::decode_block(key).unwrap())
+    }
+
+    #[test]
+    fn ssh_hosts_are_found_in_git_urls() {
+        let host = |url| ssh_host(url).map(|(host, port)| format!("{}:{}", host, port));
+
+        assert_eq!(
+            host("git@github.com:org/repo.git").unwrap(),
+            "github.com:22"
+        );
+        assert_eq!(
+            host("ssh://git@github.com/org/repo").unwrap(),
+            "github.com:22"
+        );
+        assert_eq!(
+            host("ssh://git@git.example.com:2222/repo").unwrap(),
+            "git.example.com:2222"
+        );
+        assert_eq!(host("ssh://[::1]:2222/repo").unwrap(), "::1:2222");
+        assert_eq!(host("https://github.com/org/repo"), None);
+        assert_eq!(host("file:///tmp/repo"), None);
+        assert_eq!(host("./some/dir:with-colon"), None);
+    }
+
+    #[test]
+    fn known_hosts_entries_are_matched() {
+        let key = key_sha256(KEY);
+        let lookup = |contents: &str, name| lookup(contents, name, &key);
+
+        let plain = format!("# comment\ngithub.com,140.82.121.4 ssh-ed25519 {}\n", KEY);
+        assert_eq!(lookup(&plain, "github.com"), HostKeyStatus::Known);
+        assert_eq!(lookup(&plain, "140.82.121.4"), HostKeyStatus::Known);
+        assert_eq!(lookup(&plain, "gitlab.com"), HostKeyStatus::Unknown);
+        assert_eq!(lookup(&plain, "[github.com]:2222"), HostKeyStatus::Unknown);
+
+        let ported = format!("[git.example.com]:2222 ssh-ed25519 {}", KEY);
+        assert_eq!(
+            lookup(&ported, "[git.example.com]:2222"),
+            HostKeyStatus::Known
+        );
+
+        let wildcard = format!("*.example.com,!evil.example.com ssh-ed25519 {}", KEY);
+        assert_eq!(lookup(&wildcard, "git.example.com"), HostKeyStatus::Known);
+        assert_eq!(
+            lookup(&wildcard, "evil.example.com"),
+            HostKeyStatus::Unknown
+        );
+
+        let hashed = format!(
+            "|1|L09tAkVN+hudEV2yx4EkOkeLIbw=|YUiL+9Aby3wBNsDr4lIoapYjocU= ssh-ed25519 {}",
+            KEY
+        );
+        assert_eq!(lookup(&hashed, "example.com"), HostKeyStatus::Known);
+        assert_eq!(lookup(&hashed, "example.org"), HostKeyStatus::Unknown);
+
+        let changed = format!("github.com ssh-ed25519 {}", OTHER_KEY);
+        assert_eq!(lookup(&changed, "github.com"), HostKeyStatus::Mismatch);
+        let rotated = format!("{}\n{}", changed, plain);
+        assert_eq!(lookup(&rotated, "github.com"), HostKeyStatus::Known);
+
+        let revoked = format!("{}@revoked * ssh-ed25519 {}\n", plain, KEY);
+        assert_eq!(lookup(&revoked, "github.com"), HostKeyStatus::Revoked);
+    }
+
+    #[test]
+    fn unknown_and_changed_host_keys_are_rejected() {
+        let dir = TempDir::new("known-hosts").unwrap();
+        let known_hosts = dir.path().join("known_hosts");
+        fs::write(&known_hosts, format!("github.com ssh-ed25519 {}\n", KEY)).unwrap();
+
+        assert!(verify(&known_hosts, "github.com", 22, &key_sha256(KEY), false).is_ok());
+
+        match verify(&known_hosts, "github.com", 22, &key_sha256(OTHER_KEY), true) {
+            Err(BlueprintSourceError::HostKeyMismatch { host, fingerprint }) => {
+                assert_eq!(host, "github.com");
+                assert_eq!(
+                    fingerprint,
+                    "SHA256:JayWA/NY2goqbyl+/MztzvZz5fVPT8YtDVmmvvb2WcM"
+                );
+            }
+            _ => panic!("expected a host key mismatch"),
+        }
+
+        match verify(&known_hosts, "gitlab.com", 2222, &key_sha256(KEY), false) {
+            Err(BlueprintSourceError::UnknownHostKey { host, fingerprint }) => {
+                assert_eq!(host, "[gitlab.com]:2222");
+                assert_eq!(
+                    fingerprint,
+                    "SHA256:LNVJ8dEq8IAyNlpi/s0FxB5YwMvA0KfUWgA2IPOdj/0"
+                );
+            }
+            _ => panic!("expected an unknown host key"),
+        }
+    }
+}
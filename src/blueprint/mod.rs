@@ -1,44 +1,110 @@
+mod archive;
+pub mod cache;
+pub mod condition;
+mod credentials;
+mod download;
+pub mod encryption;
+mod known_hosts;
+mod prompt;
 pub mod source;
 mod values;
+pub mod version;
 
 use std::clone::Clone;
+use std::collections::HashSet;
 use std::error::Error;
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::fs;
-use std::io::Write;
+use std::io::{Read, Write};
 use std::path::Path;
 use std::path::PathBuf;
 use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
 
+use chrono::Local;
 use git2::{IndexAddOption, Oid, Repository, Signature};
+use indexmap::IndexMap;
 use log::{debug, info};
 use serde::{Deserialize, Serialize};
 use serde_yaml;
+use tempdir::TempDir;
 use thiserror::Error;
 use walkdir::{DirEntry, WalkDir};
 
+use crate::blueprint::cache::Cache;
+use crate::blueprint::condition::Condition;
+use crate::blueprint::encryption::{EncryptionError, LocalKey};
 use crate::blueprint::source::BlueprintSourceError;
+use crate::blueprint::version::{BlueprintVersion, TagPattern, VersionRange};
+use crate::pattern::{MatchOptions, PatternError};
 use crate::templating::TemplatingEngine;
 use crate::Pattern;
+pub use prompt::PromptError;
 use source::Source;
-pub use values::Values;
+pub use values::{StoredValue, StoredValues, Value, Values};
 
 type DynError = Box<dyn Error>;
 
+/// The file next to `metadata.yaml` that lists files to ignore, like the
+/// `ignore` list in the metadata.
+pub const IGNORE_FILE: &str = ".rendrignore";
+
+/// The reserved top-level key for the values rendr provides to every template.
+pub const CONTEXT_VALUES_KEY: &str = "rendr";
+
+/// The name of the project being rendered, which defaults to the name of the
+/// output directory.
+pub const PROJECT_NAME_VALUE: &str = "rendr.project.name";
+
+/// The name of the preset the project was rendered with, if any.
+pub const PRESET_VALUE: &str = "rendr.preset";
+
+/// The context values that depend on when and where a project was rendered.
+/// They're recorded in `.rendr.yaml`, so that checks and upgrades see them as
+/// they were.
+const RECORDED_CONTEXT_VALUES: &[&str] = &["now", "git"];
+
 pub struct Blueprint {
     pub auth: Option<BlueprintAuth>,
     pub metadata: BlueprintMetadata,
     pub source: Source,
     pub pre_render_script: Option<Script>,
     pub post_render_script: Option<Script>,
+    /// The blueprint this one `extends`, if any.
+    pub parent: Option<Box<Blueprint>>,
+    /// The template variants a project was rendered with, as recorded in its
+    /// `.rendr.yaml` file. Otherwise they're chosen by the values.
+    pub variants: Option<Vec<String>>,
 }
 
 impl Blueprint {
     pub fn new(source: &str, auth: Option<BlueprintAuth>) -> Result<Blueprint, BlueprintInitError> {
+        Self::with_cache(source, auth, &Cache::default())
+    }
+
+    /// Like `new`, but remote blueprints are fetched into the given cache.
+    pub fn with_cache(
+        source: &str,
+        auth: Option<BlueprintAuth>,
+        cache: &Cache,
+    ) -> Result<Blueprint, BlueprintInitError> {
+        Self::load(source, auth, cache, &mut Vec::new())
+    }
+
+    /// Loads a blueprint and the blueprints it extends. `children` are the
+    /// blueprints extending this one, to catch cycles.
+    fn load(
+        source: &str,
+        auth: Option<BlueprintAuth>,
+        cache: &Cache,
+        children: &mut Vec<PathBuf>,
+    ) -> Result<Blueprint, BlueprintInitError> {
         debug!("Initializing blueprint from source {}", source);
 
-        let source = Source::new(source, auth.clone())?;
+        let source = Source::with_cache(source, auth.clone(), cache)?;
         let metadata_path = source.path().join("metadata.yaml");
 
         debug!(
@@ -48,7 +114,56 @@ impl Blueprint {
         let meta_raw = fs::read_to_string(metadata_path)?;
 
         debug!("Loaded blueprint metadata: {}", meta_raw);
-        let metadata = serde_yaml::from_str(&meta_raw)?;
+        let mut metadata: BlueprintMetadata = serde_yaml::from_str(&meta_raw)?;
+        metadata.check_rendr_version(&rendr_version())?;
+
+        let ignore_file = source.path().join(IGNORE_FILE);
+        if ignore_file.is_file() {
+            let patterns = fs::read_to_string(&ignore_file)?;
+            metadata.ignore.extend(parse_ignore_file(&patterns)?);
+        }
+
+        if let Some(value) = metadata
+            .values
+            .iter()
+            .find(|v| v.name.split('.').next() == Some(CONTEXT_VALUES_KEY))
+        {
+            return Err(BlueprintInitError::ReservedValueName(value.name.clone()));
+        }
+
+        if let Some(variant) = metadata
+            .variants
+            .iter()
+            .find(|v| !source.path().join("template").join(&v.root).is_dir())
+        {
+            return Err(BlueprintInitError::MissingVariant(variant.root.clone()));
+        }
+
+        let parent = match &metadata.extends {
+            Some(extends) => {
+                let path = source
+                    .path()
+                    .canonicalize()
+                    .map_err(BlueprintInitError::MetadataReadError)?;
+                if children.contains(&path) {
+                    return Err(BlueprintInitError::CyclicExtends(extends.clone()));
+                }
+                children.push(path);
+
+                // Relative paths are relative to the extending blueprint.
+                let relative = source.path().join(extends);
+                let parent_source = match relative.exists() {
+                    true => relative.display().to_string(),
+                    false => extends.clone(),
+                };
+                debug!("Blueprint extends {}", parent_source);
+
+                let parent = Self::load(&parent_source, auth.clone(), cache, children)?;
+                metadata.inherit(&parent.metadata);
+                Some(Box::new(parent))
+            }
+            None => None,
+        };
 
         let mut blueprint = Blueprint {
             auth,
@@ -56,6 +171,8 @@ impl Blueprint {
             source,
             pre_render_script: None,
             post_render_script: None,
+            parent,
+            variants: None,
         };
 
         blueprint.find_scripts()?;
@@ -67,12 +184,6 @@ impl Blueprint {
         self.source.path()
     }
 
-    pub fn set_source(&mut self, source: &str) -> Result<(), BlueprintInitError> {
-        self.source = Source::new(source, self.auth.clone())?;
-
-        Ok(())
-    }
-
     fn find_scripts(&mut self) -> Result<(), BlueprintInitError> {
         self.pre_render_script = self.find_script("pre-render.sh")?;
         self.post_render_script = self.find_script("post-render.sh")?;
@@ -91,6 +202,11 @@ impl Blueprint {
         script_path.push("scripts");
         script_path.push(format!("{}", script));
 
+        // Scripts that aren't overridden come from the parent blueprint.
+        if let (false, Some(parent)) = (script_path.exists(), &self.parent) {
+            return parent.find_script(script);
+        }
+
         if !script_path.exists() {
             debug!(
                 "No {} script found in blueprint scripts directory - skipping",
@@ -103,34 +219,358 @@ impl Blueprint {
     }
 
     pub fn is_git_init_enabled(&self) -> bool {
-        self.metadata.git_init
+        self.metadata.git_init.unwrap_or(false)
     }
 
     pub fn values(&self) -> impl Iterator<Item = &ValueSpec> {
         self.metadata.values.iter()
     }
 
-    pub fn default_values(&self) -> impl Iterator<Item = (&str, &str)> {
+    /// The default values for everything that wasn't `provided` already.
+    /// Defaults coming from the environment or from commands are only
+    /// evaluated when the iterator is consumed, and only for the values that
+    /// are actually missing.
+    pub fn default_values<'a>(
+        &'a self,
+        working_dir: &'a Path,
+        provided: &'a Values,
+    ) -> impl Iterator<Item = (&'a str, Value)> + 'a {
         self.values()
-            .filter(|v| v.default.is_some())
-            .map(|v| (v.name.as_str(), v.default.as_ref().unwrap().as_str()))
+            .filter(move |v| !provided.contains(&v.name))
+            .filter_map(move |v| v.default_value(working_dir).map(|d| (v.name.as_str(), d)))
+    }
+
+    /// The values of the named preset, if the blueprint declares it.
+    pub fn preset(&self, name: &str) -> Option<&Values> {
+        self.metadata.presets.get(name)
+    }
+
+    pub fn preset_names(&self) -> impl Iterator<Item = &str> {
+        self.metadata.presets.keys().map(String::as_str)
     }
 
     pub fn required_values(&self) -> impl Iterator<Item = &ValueSpec> {
         self.values().filter(|v| v.required)
     }
 
-    pub fn files(&self) -> impl Iterator<Item = Result<File, walkdir::Error>> {
-        let template_root = self.source.path().join("template");
-        Files::new(&template_root)
+    pub fn secret_values(&self) -> impl Iterator<Item = &ValueSpec> {
+        self.values().filter(|v| v.secret)
+    }
+
+    /// Completes the `provided` values: missing ones get the blueprint's
+    /// defaults, secrets are looked up in the environment, and the user is
+    /// prompted for the required and secret values that are still missing.
+    pub fn complete_values(
+        &self,
+        working_dir: &Path,
+        provided: Values,
+    ) -> Result<Values, PromptError> {
+        self.fill_values(working_dir, provided, true)
+    }
+
+    /// Completes the `provided` values like `complete_values`, but fails on
+    /// the first required or secret value that's still missing instead of
+    /// prompting for it.
+    pub fn complete_values_without_prompts(
+        &self,
+        working_dir: &Path,
+        provided: Values,
+    ) -> Result<Values, PromptError> {
+        self.fill_values(working_dir, provided, false)
+    }
+
+    fn fill_values(
+        &self,
+        working_dir: &Path,
+        provided: Values,
+        prompt: bool,
+    ) -> Result<Values, PromptError> {
+        let mut values: Values = self.default_values(working_dir, &provided).collect();
+        values.merge(&provided);
+
+        // Secret values are never stored in .rendr.yaml, so try the environment.
+        let env_values: Vec<_> = prompt::secret_values_from_env(
+            self.secret_values().filter(|v| !values.contains(&v.name)),
+        )
+        .collect();
+        for (name, value) in env_values {
+            values.insert(name, value);
+        }
+
+        // Prompt for the values that are still missing and merge them in.
+        let mut missing_values = self
+            .values()
+            .filter(|v| (v.required || v.secret) && !values.contains(&v.name));
+        if !prompt {
+            return match missing_values.next() {
+                Some(value) => Err(PromptError::not_provided(value)),
+                None => Ok(values),
+            };
+        }
+        let prompt_values =
+            prompt::prompt_for_values(missing_values).collect::<Result<Vec<_>, _>>()?;
+        for (name, value) in prompt_values {
+            values.insert(name, value);
+        }
+
+        Ok(values)
+    }
+
+    /// The values to render the blueprint with, in a reproducible order: as
+    /// declared in the metadata, with any extra values sorted by name after
+    /// them. The built-in context values are added under the reserved
+    /// `rendr` key.
+    pub fn prepare_values(&self, values: &Values, output_dir: &Path) -> Values {
+        let mut values = values.clone();
+        values.sort_by_declaration(self.values().map(|v| v.name.as_str()));
+
+        // The project name and preset are the only context values that may be
+        // provided by the caller; everything else under `rendr` is ours.
+        let project_name = values.get(PROJECT_NAME_VALUE).cloned();
+        let preset = values.get(PRESET_VALUE).cloned();
+        values.remove(CONTEXT_VALUES_KEY);
+        values.merge(&self.context_values(output_dir, project_name));
+        if let Some(preset) = preset {
+            values.insert(PRESET_VALUE, preset);
+        }
+
+        values
+    }
+
+    /// Values that rendr already knows about, which are available to every
+    /// template without the blueprint declaring them.
+    fn context_values(&self, output_dir: &Path, project_name: Option<String>) -> Values {
+        let mut context = Values::new();
+        let now = Local::now();
+
+        context.insert("rendr.version", env!("CARGO_PKG_VERSION"));
+        context.insert("rendr.blueprint.name", self.metadata.name.as_str());
+        context.insert("rendr.blueprint.version", self.metadata.version.to_string());
+        context.insert("rendr.now.year", now.format("%Y").to_string());
+        context.insert("rendr.now.date", now.format("%Y-%m-%d").to_string());
+
+        let dir_name = output_dir
+            .canonicalize()
+            .ok()
+            .and_then(|dir| dir.file_name().map(|n| n.to_string_lossy().to_string()));
+        if let Some(name) = project_name.or_else(|| dir_name.clone()) {
+            context.insert(PROJECT_NAME_VALUE, name);
+        }
+        if let Some(dir_name) = dir_name {
+            context.insert("rendr.project.dir_name", dir_name);
+        }
+
+        if let Ok(config) = git2::Config::open_default() {
+            for (key, path) in &[
+                ("user.name", "rendr.git.user.name"),
+                ("user.email", "rendr.git.user.email"),
+            ] {
+                if let Ok(value) = config.get_string(key) {
+                    context.insert(path, value);
+                }
+            }
+        }
+
+        context
+    }
+
+    fn secret_value_names(&self) -> Vec<&str> {
+        self.secret_values().map(|v| v.name.as_str()).collect()
+    }
+
+    /// The blueprint's template files, without the ignored ones. Those of an
+    /// extended blueprint are included, unless the blueprint has its own file
+    /// of the same name, or `remove`s it.
+    pub fn files(&self) -> Box<dyn Iterator<Item = Result<File, walkdir::Error>> + '_> {
+        self.files_with(None)
     }
 
-    pub fn is_excluded<P: AsRef<Path>>(&self, file: P) -> bool {
+    /// The files of the template variants chosen by the values, or of all
+    /// variants if there are no values.
+    fn files_with<'a>(
+        &'a self,
+        values: Option<&'a Values>,
+    ) -> Box<dyn Iterator<Item = Result<File, walkdir::Error>> + 'a> {
+        let files = self.overlaid_files(values).filter(move |file| match file {
+            Ok(file) => !self.is_ignored(file.path_from_template_root()),
+            Err(_) => true,
+        });
+
+        Box::new(files)
+    }
+
+    fn overlaid_files<'a>(
+        &'a self,
+        values: Option<&'a Values>,
+    ) -> Box<dyn Iterator<Item = Result<File, walkdir::Error>> + 'a> {
+        let roots = self.template_roots(values);
+        if let ([root], None) = (roots.as_slice(), &self.parent) {
+            return Box::new(Files::new(root));
+        }
+
+        // Variants later in the list override the files of earlier ones.
+        let mut own: HashSet<PathBuf> = HashSet::new();
+        let mut files = Vec::new();
+        for root in roots.iter().rev() {
+            for file in Files::new(root) {
+                match file {
+                    Ok(file) if own.insert(file.path_from_template_root.clone()) => {
+                        files.push(Ok(file))
+                    }
+                    Ok(_) => {}
+                    Err(e) => files.push(Err(e)),
+                }
+            }
+        }
+
+        let parent = match &self.parent {
+            Some(parent) => parent,
+            None => return Box::new(files.into_iter()),
+        };
+        let inherited = parent.files_with(values).filter(move |file| match file {
+            Ok(file) => {
+                !own.contains(file.path_from_template_root())
+                    && !self.is_removed(file.path_from_template_root())
+            }
+            Err(_) => true,
+        });
+
+        Box::new(files.into_iter().chain(inherited))
+    }
+
+    /// The template variants chosen by the values, unless they were recorded
+    /// for the project. `None` if the blueprint has no variants.
+    pub fn variants_for(&self, values: &Values) -> Option<Vec<String>> {
+        if self.metadata.variants.is_empty() {
+            return None;
+        }
+
+        Some(self.chosen_variants(Some(values)))
+    }
+
+    /// Whether the blueprint has a template variant with the given root.
+    pub fn has_variant(&self, root: &str) -> bool {
+        self.metadata.variants.iter().any(|v| v.root == root)
+    }
+
+    fn chosen_variants(&self, values: Option<&Values>) -> Vec<String> {
+        if let Some(variants) = &self.variants {
+            return variants.clone();
+        }
+
         self.metadata
-            .exclusions
+            .variants
+            .iter()
+            .filter(|variant| match (&variant.when, values) {
+                (Some(condition), Some(values)) => condition.evaluate(values),
+                _ => true,
+            })
+            .map(|variant| variant.root.clone())
+            .collect()
+    }
+
+    /// The directories the template files are in: `template` itself, or the
+    /// chosen variants in it.
+    fn template_roots(&self, values: Option<&Values>) -> Vec<PathBuf> {
+        let template = self.source.path().join("template");
+        if self.metadata.variants.is_empty() {
+            return vec![template];
+        }
+
+        self.chosen_variants(values)
+            .iter()
+            .map(|root| template.join(root))
+            .collect()
+    }
+
+    /// The path of a template file, which may come from a variant or an
+    /// extended blueprint.
+    pub fn template_path<P: AsRef<Path>>(&self, file: P) -> PathBuf {
+        let roots = self.template_roots(None);
+        let path = roots
             .iter()
-            .find(|pattern| pattern.matches_path(file.as_ref()))
-            .is_some()
+            .rev()
+            .map(|root| root.join(file.as_ref()))
+            .find(|path| path.exists())
+            .unwrap_or_else(|| roots[roots.len() - 1].join(file.as_ref()));
+
+        match &self.parent {
+            Some(parent) if !path.exists() && !self.is_removed(file.as_ref()) => {
+                parent.template_path(file)
+            }
+            _ => path,
+        }
+    }
+
+    /// The template files to render with the given values, leaving out those
+    /// whose `include_if` conditions don't hold.
+    pub fn included_files<'a>(
+        &'a self,
+        values: &'a Values,
+    ) -> impl Iterator<Item = Result<File, walkdir::Error>> + 'a {
+        self.files_with(Some(values))
+            .filter(move |file| match file {
+                Ok(file) => self.is_included(file.path_from_template_root(), values),
+                Err(_) => true,
+            })
+    }
+
+    /// Whether a file is rendered with the given values: a file is left out if
+    /// it or one of its directories matches an `include_if` pattern, and the
+    /// pattern's condition doesn't hold.
+    pub fn is_included<P: AsRef<Path>>(&self, file: P, values: &Values) -> bool {
+        let file = file.as_ref();
+        let is_dir = self.template_path(file).is_dir();
+        // A directory also matches patterns for everything in it, like
+        // `docker/**`, so it isn't created empty.
+        let contents = file.join("**");
+        let options = self.metadata.match_options();
+
+        self.metadata.include_if.iter().all(|(pattern, condition)| {
+            let pattern = std::slice::from_ref(pattern);
+            let matches = Pattern::list_matches(pattern, file, is_dir, options)
+                || (is_dir && Pattern::list_matches(pattern, &contents, false, options));
+            !matches || condition.evaluate(values)
+        })
+    }
+
+    /// Whether a file is ignored, because it or one of its directories matches
+    /// the `ignore` patterns.
+    pub fn is_ignored<P: AsRef<Path>>(&self, file: P) -> bool {
+        let file = file.as_ref();
+
+        Pattern::list_matches(
+            &self.metadata.ignore,
+            file,
+            self.template_path(file).is_dir(),
+            self.metadata.match_options(),
+        )
+    }
+
+    /// Whether an extended blueprint's file is left out, because it or one of
+    /// its directories matches the `remove` patterns.
+    fn is_removed(&self, file: &Path) -> bool {
+        let is_dir = match &self.parent {
+            Some(parent) => parent.template_path(file).is_dir(),
+            None => return false,
+        };
+
+        Pattern::list_matches(
+            &self.metadata.remove,
+            file,
+            is_dir,
+            self.metadata.match_options(),
+        )
+    }
+
+    /// Whether a file is copied as it is, without templating.
+    pub fn is_verbatim<P: AsRef<Path>>(&self, file: P) -> bool {
+        Pattern::list_matches(
+            &self.metadata.verbatim,
+            file.as_ref(),
+            false,
+            self.metadata.match_options(),
+        )
     }
 
     pub fn render<'s, TE: TemplatingEngine>(
@@ -148,13 +588,15 @@ impl Blueprint {
             fs::create_dir(output_dir)?;
         }
 
+        let values = &self.prepare_values(values, output_dir);
+
         // Run pre-render script
         if let Some(pre_render_script) = &self.pre_render_script {
-            pre_render_script.run(output_dir, &values)?;
+            pre_render_script.run(output_dir, values, &self.secret_value_names())?;
         }
 
         // Render each file in blueprint template
-        for file in self.files() {
+        for file in self.included_files(values) {
             let file = file?;
             let path = file.path();
             let output_path = output_dir.join(file.path_from_template_root());
@@ -162,7 +604,7 @@ impl Blueprint {
             if path.is_file() {
                 debug!("Found file {:?}", &file.path_from_template_root);
 
-                if self.is_excluded(&file.path_from_template_root) {
+                if self.is_verbatim(&file.path_from_template_root) {
                     debug!(
                         "Copying {:?} without templating.",
                         &file.path_from_template_root
@@ -197,7 +639,7 @@ impl Blueprint {
 
         // Run post-render script
         if let Some(post_render_script) = &self.post_render_script {
-            post_render_script.run(output_dir, &values)?;
+            post_render_script.run(output_dir, values, &self.secret_value_names())?;
         }
 
         Ok(())
@@ -209,19 +651,102 @@ impl Blueprint {
         values: &Values,
         output_dir: &Path,
         source: &str,
-        source_version: &u32,
+        source_version: &BlueprintVersion,
         dry_run: bool,
     ) -> Result<(), DynError> {
+        let values = &self.prepare_values(values, output_dir);
+
         info!("Upgrading to blueprint version {}", &self.metadata.version);
         debug!("Root project dir {:?}", &output_dir);
 
-        for file in self.files() {
+        self.render_new_files(engine, values, output_dir, dry_run)?;
+
+        self.generate_rendr_file(source, output_dir, values, dry_run)?;
+
+        let target_version = &self.metadata.version;
+        let scripts = self.get_upgrade_scripts(source_version, target_version);
+        self.run_upgrade_scripts(scripts, output_dir, values, dry_run)?;
+
+        Ok(())
+    }
+
+    /// Renders an add-on blueprint into an existing project, and records it in
+    /// the project's `.rendr.yaml` file. Files the project already has are
+    /// left alone.
+    pub fn render_addon<TE: TemplatingEngine>(
+        &self,
+        engine: &TE,
+        values: &Values,
+        output_dir: &Path,
+        dry_run: bool,
+    ) -> Result<(), DynError> {
+        let values = &self.prepare_values(values, output_dir);
+
+        if let Some(pre_render_script) = &self.pre_render_script {
+            if !dry_run {
+                pre_render_script.run(output_dir, values, &self.secret_value_names())?;
+            }
+        }
+
+        self.render_new_files(engine, values, output_dir, dry_run)?;
+        self.record_addon(output_dir, values, dry_run)?;
+
+        if let Some(post_render_script) = &self.post_render_script {
+            if !dry_run {
+                post_render_script.run(output_dir, values, &self.secret_value_names())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Like `render_upgrade`, for an add-on blueprint.
+    pub fn render_addon_upgrade<TE: TemplatingEngine>(
+        &self,
+        engine: &TE,
+        values: &Values,
+        output_dir: &Path,
+        source_version: &BlueprintVersion,
+        dry_run: bool,
+    ) -> Result<(), DynError> {
+        let values = &self.prepare_values(values, output_dir);
+
+        info!(
+            "Upgrading add-on {} to version {}",
+            &self.metadata.name, &self.metadata.version
+        );
+
+        self.render_new_files(engine, values, output_dir, dry_run)?;
+        self.record_addon(output_dir, values, dry_run)?;
+
+        let target_version = &self.metadata.version;
+        let scripts = self.get_upgrade_scripts(source_version, target_version);
+        self.run_upgrade_scripts(scripts, output_dir, values, dry_run)?;
+
+        Ok(())
+    }
+
+    /// Renders the template files that don't exist in the output directory
+    /// yet. Verbatim files are copied without templating.
+    fn render_new_files<TE: TemplatingEngine>(
+        &self,
+        engine: &TE,
+        values: &Values,
+        output_dir: &Path,
+        dry_run: bool,
+    ) -> Result<(), DynError> {
+        for file in self.included_files(values) {
             let file = file?;
             let path = file.path();
             let output_path = output_dir.join(file.path_from_template_root());
 
             if path.is_file() {
-                if self.is_excluded(&file.path_from_template_root) {
+                if output_path.exists() {
+                    debug!(
+                        "Skipping {:?}, file already exists",
+                        &file.path_from_template_root
+                    );
+                } else if self.is_verbatim(&file.path_from_template_root) {
                     debug!(
                         "Copying {:?} without templating.",
                         &file.path_from_template_root
@@ -229,11 +754,6 @@ impl Blueprint {
                     if !dry_run {
                         fs::copy(path, output_path)?;
                     }
-                } else if output_path.exists() {
-                    debug!(
-                        "Skipping {:?}, file already exists",
-                        &file.path_from_template_root
-                    );
                 } else {
                     debug!(
                         "Using template {:?} to render {:?}",
@@ -255,11 +775,57 @@ impl Blueprint {
             }
         }
 
-        self.generate_rendr_file(&source, &output_dir, &values, dry_run)?;
+        Ok(())
+    }
 
-        let target_version = self.metadata.version;
-        let scripts = self.get_upgrade_scripts(&source_version, &target_version);
-        self.run_upgrade_scripts(scripts, output_dir, values, dry_run)?;
+    /// Adds the add-on to the project's `.rendr.yaml` file, or updates it if
+    /// it's there already. Only the add-on's own values are stored with it,
+    /// without secrets.
+    fn record_addon(
+        &self,
+        output_dir: &Path,
+        values: &Values,
+        dry_run: bool,
+    ) -> Result<(), DynError> {
+        let path = output_dir.join(Path::new(".rendr.yaml"));
+        let mut config = match RendrConfig::load(&path)? {
+            Some(config) => config,
+            None => {
+                return Err(format!("no .rendr.yaml file found in {}", output_dir.display()).into())
+            }
+        };
+
+        let mut own_values = Values::new();
+        for spec in self.values().filter(|v| !v.secret) {
+            if let Some(value) = values.get_value(&spec.name) {
+                own_values.insert(&spec.name, value.clone());
+            }
+        }
+
+        let previous_range = config
+            .addon(&self.metadata.name)
+            .and_then(|a| a.version_range.as_ref());
+        let addon = AddonConfig {
+            name: self.metadata.name.clone(),
+            version: self.metadata.version.clone(),
+            version_range: Some(self.metadata.version.range_from(previous_range)),
+            source: self.source.to_string(output_dir),
+            source_path: self.source.subdir().map(str::to_string),
+            source_ref: self.source.git_ref().map(str::to_string),
+            source_commit: self.source.commit(),
+            source_sha256: self.source.sha256().map(str::to_string),
+            values: StoredValues::from(&own_values),
+        };
+        debug!("Recording add-on {} {}", addon.name, addon.version);
+
+        match config.addons.iter_mut().find(|a| a.name == addon.name) {
+            Some(existing) => *existing = addon,
+            None => config.addons.push(addon),
+        }
+
+        if !dry_run {
+            config.save(&path)?;
+        }
 
         Ok(())
     }
@@ -285,7 +851,9 @@ impl Blueprint {
         repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &[])
     }
 
-    fn generate_rendr_file<'s>(
+    /// Writes the project's `.rendr.yaml` file for the values it was rendered
+    /// with.
+    pub fn generate_rendr_file(
         &self,
         source: &str,
         output_dir: &Path,
@@ -298,7 +866,52 @@ impl Blueprint {
         debug!("  values: {:?}", values);
 
         let path = output_dir.join(Path::new(".rendr.yaml"));
-        let config = RendrConfig::new(source.to_string().clone(), &self.metadata, values.clone());
+
+        // Values that were encrypted in an existing .rendr.yaml file stay
+        // encrypted, add-ons stay recorded and the version range is kept.
+        let (previous, addons, version_range) = match path.exists() {
+            true => RendrConfig::load(&path)?.map(|c| (c.values, c.addons, c.version_range)),
+            false => None,
+        }
+        .unwrap_or_default();
+        let encrypted = previous.encrypted_names();
+
+        // Secret values are only ever kept in memory, never written to disk,
+        // unless they're encrypted. Context values are recalculated every time,
+        // apart from the recorded ones.
+        let mut omitted: Vec<_> = self
+            .secret_value_names()
+            .into_iter()
+            .filter(|name| !encrypted.contains(name))
+            .collect();
+        omitted.push(CONTEXT_VALUES_KEY);
+        let mut stored_values = StoredValues::from(&values.without(&omitted));
+
+        if !encrypted.is_empty() {
+            let key = LocalKey::load(LocalKey::default_path()?)?;
+            stored_values.encrypt_like(&previous, &key)?;
+        }
+
+        let mut config =
+            RendrConfig::new(source.to_string().clone(), &self.metadata, stored_values);
+        config.preset = values.get(PRESET_VALUE).cloned();
+        // The project name is only stored if it isn't the directory name.
+        config.project_name = values
+            .get(PROJECT_NAME_VALUE)
+            .filter(|name| Some(*name) != values.get("rendr.project.dir_name"))
+            .cloned();
+        for name in RECORDED_CONTEXT_VALUES {
+            if let Some(value) = values.get_value(&format!("{}.{}", CONTEXT_VALUES_KEY, name)) {
+                config.context.insert(name, value);
+            }
+        }
+        config.source_path = self.source.subdir().map(str::to_string);
+        config.source_ref = self.source.git_ref().map(str::to_string);
+        config.source_commit = self.source.commit();
+        config.source_sha256 = self.source.sha256().map(str::to_string);
+        config.version_range = Some(self.metadata.version.range_from(version_range.as_ref()));
+        config.addons = addons;
+        config.variants = self.variants_for(values);
         let yaml = serde_yaml::to_string(&config)?;
 
         if !dry_run {
@@ -309,12 +922,25 @@ impl Blueprint {
         Ok(())
     }
 
-    pub fn get_upgrade_scripts(&self, source_version: &u32, target_version: &u32) -> Vec<&UpgradeSpec> {
-        self.metadata
+    /// The upgrade scripts for the versions after the source version, up to
+    /// and including the target version, in version order. Scripts limited to
+    /// upgrades `from` a range of versions are skipped if the source version
+    /// isn't in it.
+    pub fn get_upgrade_scripts(
+        &self,
+        source_version: &BlueprintVersion,
+        target_version: &BlueprintVersion,
+    ) -> Vec<&UpgradeSpec> {
+        let mut scripts: Vec<_> = self
+            .metadata
             .upgrades
             .iter()
-            .filter(|it| it.version > *source_version && it.version <= *target_version)
-            .collect()
+            .filter(|it| &it.version > source_version && &it.version <= target_version)
+            .filter(|it| it.from.as_ref().is_none_or(|r| r.matches(source_version)))
+            .collect();
+        scripts.sort_by_key(|it| &it.version);
+
+        scripts
     }
 
     fn run_upgrade_scripts(
@@ -324,7 +950,7 @@ impl Blueprint {
         values: &Values,
         dry_run: bool,
     ) -> Result<(), DynError> {
-        let target_version = self.metadata.version;
+        let target_version = &self.metadata.version;
         debug!(
             "Running {} upgrade script(s) for version {}",
             scripts.len(),
@@ -336,7 +962,7 @@ impl Blueprint {
                 s.executable = Some(String::from(&script.executable));
                 println!("Running upgrade script: {}", s.name);
                 if !dry_run {
-                    s.run(output_dir, values)?;
+                    s.run(output_dir, values, &self.secret_value_names())?;
                 }
             }
         }
@@ -345,6 +971,21 @@ impl Blueprint {
     }
 }
 
+/// Reads the patterns of a `.rendrignore` file, one per line. Blank lines and
+/// lines starting with `#` are skipped.
+fn parse_ignore_file(contents: &str) -> Result<Vec<Pattern>, BlueprintInitError> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            line.parse().map_err(|e: PatternError| {
+                BlueprintInitError::InvalidIgnorePattern(line.to_string(), e.to_string())
+            })
+        })
+        .collect()
+}
+
 #[derive(Clone)]
 pub struct BlueprintAuth {
     user: Option<String>,
@@ -375,38 +1016,209 @@ pub enum BlueprintInitError {
 
     #[error("error looking up blueprint scripts")]
     ScriptLookupError(#[source] std::io::Error),
+
+    #[error("the value name `{0}` is reserved for rendr's built-in values")]
+    ReservedValueName(String),
+
+    #[error("the blueprint requires rendr {required} or newer, but this is rendr {current}; please upgrade rendr")]
+    RendrTooOld {
+        required: BlueprintVersion,
+        current: BlueprintVersion,
+    },
+
+    #[error("invalid pattern `{0}` in .rendrignore: {1}")]
+    InvalidIgnorePattern(String, String),
+
+    #[error("the template variant `{0}` isn't a directory in the template")]
+    MissingVariant(String),
+
+    #[error("extending `{0}` creates a cycle of blueprints")]
+    CyclicExtends(String),
+
+    #[error("the blueprint supports rendr up to {supported}, but this is rendr {current}")]
+    RendrTooNew {
+        supported: BlueprintVersion,
+        current: BlueprintVersion,
+    },
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct RendrConfig {
     pub name: String,
-    pub version: u32,
+    pub version: BlueprintVersion,
+    /// The blueprint versions the project may be upgraded to without
+    /// `--allow-major`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_range: Option<VersionRange>,
     pub author: String,
     pub description: String,
     pub source: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_ref: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_commit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_sha256: Option<String>,
     pub rendr_version: String,
-    values: Values,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    /// The project name the templates were rendered with, if it isn't the
+    /// name of the project directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project_name: Option<String>,
+    /// The context values that depend on when and where the templates were
+    /// rendered, like `now.year` and `git.user.name`.
+    #[serde(default, skip_serializing_if = "Values::is_empty")]
+    pub context: Values,
+    values: StoredValues,
+    /// The add-on blueprints rendered into the project with `rendr add`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub addons: Vec<AddonConfig>,
+    /// The template variants the project was rendered with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variants: Option<Vec<String>>,
+}
+
+/// An add-on blueprint in a project. It's upgraded separately from the
+/// project's own blueprint.
+#[derive(Serialize, Deserialize)]
+pub struct AddonConfig {
+    pub name: String,
+    pub version: BlueprintVersion,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_range: Option<VersionRange>,
+    pub source: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_ref: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_commit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_sha256: Option<String>,
+    #[serde(default)]
+    values: StoredValues,
+}
+
+impl AddonConfig {
+    pub fn values(&self) -> &StoredValues {
+        &self.values
+    }
+
+    /// The add-on's source, pinned like `RendrConfig::pinned_source`.
+    pub fn pinned_source(&self) -> String {
+        pinned_locator(
+            &self.source,
+            self.source_path.as_deref(),
+            self.source_commit.as_deref(),
+            self.source_sha256.as_deref(),
+        )
+    }
+}
+
+fn pinned_locator(
+    source: &str,
+    path: Option<&str>,
+    commit: Option<&str>,
+    sha256: Option<&str>,
+) -> String {
+    let pin = match (commit, sha256) {
+        (Some(commit), _) => Some(commit.to_string()),
+        (None, Some(sha256)) => Some(format!("sha256={}", sha256)),
+        (None, None) => None,
+    };
+
+    source::locator(source, path, pin.as_deref())
 }
 
 impl RendrConfig {
-    fn new(source: String, metadata: &BlueprintMetadata, values: Values) -> Self {
+    fn new(source: String, metadata: &BlueprintMetadata, values: StoredValues) -> Self {
         RendrConfig {
             name: metadata.name.clone(),
-            version: metadata.version,
+            version: metadata.version.clone(),
+            version_range: Some(metadata.version.compatible_range()),
             rendr_version: env!("CARGO_PKG_VERSION").to_string(),
             author: metadata.author.clone(),
             description: metadata.description.clone(),
             source,
+            source_path: None,
+            source_ref: None,
+            source_commit: None,
+            source_sha256: None,
+            preset: None,
+            project_name: None,
+            context: Values::new(),
             values: values,
+            addons: Vec::new(),
+            variants: None,
         }
     }
 
-    pub fn values(&self) -> &Values {
+    pub fn values(&self) -> &StoredValues {
         &self.values
     }
 
+    pub fn values_mut(&mut self) -> &mut StoredValues {
+        &mut self.values
+    }
+
+    /// The recorded context values, under the `rendr` key like templates see
+    /// them.
+    pub fn context_values(&self) -> Values {
+        let mut values = Values::new();
+        for (name, value) in self.context.iter() {
+            values.insert(&format!("{}.{}", CONTEXT_VALUES_KEY, name), value);
+        }
+
+        values
+    }
+
+    /// Replaces the time- and machine-dependent context values with the
+    /// recorded ones. Projects rendered before they were recorded keep the
+    /// current ones.
+    pub fn restore_context(&self, values: &mut Values) {
+        if self.context.is_empty() {
+            return;
+        }
+
+        for name in RECORDED_CONTEXT_VALUES {
+            values.remove(&format!("{}.{}", CONTEXT_VALUES_KEY, name));
+        }
+        values.merge(&self.context_values());
+    }
+
+    /// The values in plain text. The local key is only loaded if some of the
+    /// values are encrypted.
+    pub fn decrypted_values(&self) -> Result<Values, EncryptionError> {
+        if self.values.encrypted_names().is_empty() {
+            return self.values.decrypted(None);
+        }
+
+        let key = LocalKey::load(LocalKey::default_path()?)?;
+        self.values.decrypted(Some(&key))
+    }
+
+    /// The blueprint source pinned to the commit the project was rendered
+    /// from if it came from a git repository, or to the checksum of the
+    /// archive if it was downloaded.
+    pub fn pinned_source(&self) -> String {
+        pinned_locator(
+            &self.source,
+            self.source_path.as_deref(),
+            self.source_commit.as_deref(),
+            self.source_sha256.as_deref(),
+        )
+    }
+
+    /// The add-on with the given name, if it's been added to the project.
+    pub fn addon(&self, name: &str) -> Option<&AddonConfig> {
+        self.addons.iter().find(|a| a.name == name)
+    }
+
     pub fn blueprint(&self) -> Result<Blueprint, BlueprintInitError> {
-        Blueprint::new(&self.source, None)
+        Blueprint::new(&self.pinned_source(), None)
     }
 
     pub fn load(metadata_file: &PathBuf) -> Result<Option<RendrConfig>, DynError> {
@@ -415,21 +1227,67 @@ impl RendrConfig {
 
         Ok(Some(config))
     }
+
+    pub fn save(&self, metadata_file: &PathBuf) -> Result<(), DynError> {
+        let yaml = serde_yaml::to_string(&self)?;
+        fs::write(metadata_file, yaml)?;
+
+        Ok(())
+    }
 }
 
 impl Display for RendrConfig {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
         writeln!(f, "name: {}", &self.name)?;
         writeln!(f, "version: {}", &self.version)?;
+        if let Some(range) = &self.version_range {
+            writeln!(f, "version_range: {}", range)?;
+        }
         writeln!(f, "description: {}", &self.description)?;
         writeln!(f, "author: {}", &self.author)?;
         writeln!(f, "source: {}", &self.source)?;
+        if let Some(path) = &self.source_path {
+            writeln!(f, "source_path: {}", path)?;
+        }
+        if let (Some(git_ref), Some(commit)) = (&self.source_ref, &self.source_commit) {
+            writeln!(f, "source_ref: {} ({})", git_ref, commit)?;
+        }
+        if let Some(preset) = &self.preset {
+            writeln!(f, "preset: {}", preset)?;
+        }
+        if let Some(project_name) = &self.project_name {
+            writeln!(f, "project_name: {}", project_name)?;
+        }
+        for (name, value) in self.context_values().flattened() {
+            writeln!(f, "{}: {}", name, value)?;
+        }
+        if let Some(variants) = &self.variants {
+            writeln!(f, "variants: {}", variants.join(", "))?;
+        }
         writeln!(f, "values:")?;
         for (name, value) in self.values.iter() {
-            writeln!(f, "- name: {}", name)?;
-            writeln!(f, "  value: {}", value)?;
+            match value {
+                StoredValue::Plain(value) => {
+                    for (name, value) in value.flatten(name) {
+                        writeln!(f, "- name: {}", name)?;
+                        writeln!(f, "  value: {}", value)?;
+                    }
+                }
+                StoredValue::Encrypted { .. } => {
+                    writeln!(f, "- name: {}", name)?;
+                    writeln!(f, "  value: <encrypted>")?;
+                }
+            }
         }
-
+        if !self.addons.is_empty() {
+            writeln!(f, "addons:")?;
+        }
+        for addon in &self.addons {
+            writeln!(f, "- name: {}", addon.name)?;
+            writeln!(f, "  version: {}", addon.version)?;
+            writeln!(f, "  source: {}", addon.source)?;
+        }
+
         Ok(())
     }
 }
@@ -493,6 +1351,9 @@ impl File {
     }
 }
 
+/// The environment variable pointing scripts to a JSON file with all the values.
+pub const VALUES_FILE_ENV_VAR: &str = "RENDR_VALUES_JSON";
+
 pub struct Script {
     executable: Option<String>,
     name: String,
@@ -508,7 +1369,10 @@ impl Script {
         }
     }
 
-    fn run<'v>(&self, working_dir: &Path, values: &Values) -> Result<(), DynError> {
+    /// Runs the script. All values are exposed as environment variables, but
+    /// the `secrets` are left out of the `--value` flags and the values file
+    /// so that they don't end up in the process list or on disk.
+    fn run(&self, working_dir: &Path, values: &Values, secrets: &[&str]) -> Result<(), DynError> {
         info!("Running blueprint script: {}", &self.name);
 
         #[cfg(debug)]
@@ -516,11 +1380,20 @@ impl Script {
         debug!("  Blueprint script full path: {:?}", &self.path);
         debug!("  Blueprint script working dir: {:?}", working_dir);
 
-        let values_flags = values
+        let public_values = values.without(secrets);
+        let values_flags = public_values
+            .flattened()
             .iter()
             .map(|i| format!("--value {}={}", i.0, i.1))
             .collect::<Vec<String>>()
             .join(" ");
+
+        // The full tree of values is also provided as a JSON file, which is
+        // easier to consume than the flattened environment variables.
+        let values_dir = TempDir::new("rendr-values")?;
+        let values_file = values_dir.path().join("values.json");
+        fs::write(&values_file, serde_json::to_string(&public_values)?)?;
+
         let command = match &self.executable {
             Some(executable) => format!("{} {} {}", executable, &self.path.display(), values_flags),
             None => format!("{}", &self.path.display()),
@@ -530,7 +1403,8 @@ impl Script {
         let output = Command::new("sh")
             .arg("-c")
             .arg(command)
-            .envs(values.map())
+            .envs(values.env_vars())
+            .env(VALUES_FILE_ENV_VAR, &values_file)
             .current_dir(working_dir)
             .stdout(Stdio::inherit())
             .stderr(Stdio::inherit())
@@ -586,33 +1460,246 @@ impl Display for Blueprint {
 #[derive(Serialize, Deserialize)]
 pub struct BlueprintMetadata {
     pub name: String,
-    pub version: u32,
+    pub version: BlueprintVersion,
     pub author: String,
     pub description: String,
     #[serde(default)]
     pub editable_templates: bool,
     pub values: Vec<ValueSpec>,
-    #[serde(default)]
-    pub exclusions: Vec<Pattern>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub presets: IndexMap<String, Values>,
+    /// Files that are copied without templating.
+    #[serde(default, alias = "exclusions")]
+    pub verbatim: Vec<Pattern>,
+    /// Files that are never rendered into projects, like test fixtures.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignore: Vec<Pattern>,
     #[serde(alias = "git-init")]
-    #[serde(default)]
-    pub git_init: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_init: Option<bool>,
     #[serde(default)]
     pub upgrades: Vec<UpgradeSpec>,
+    /// How the blueprint's git tags are named after its versions, `v{version}`
+    /// by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag_pattern: Option<TagPattern>,
+    /// The oldest rendr that can render the blueprint, for blueprints that
+    /// use newer features.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_rendr_version: Option<BlueprintVersion>,
+    /// The newest rendr the blueprint is known to work with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_rendr_version: Option<BlueprintVersion>,
+    /// The source of a blueprint this one builds on, like `../base`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
+    /// Files of the extended blueprint that aren't inherited.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remove: Vec<Pattern>,
+    /// Files that are only rendered if a condition on the values holds, like
+    /// `"docker/**": "use_docker == true"`.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub include_if: IndexMap<Pattern, Condition>,
+    /// Whether path patterns match regardless of case, e.g. on blueprints
+    /// written on case-insensitive file systems.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub case_insensitive_patterns: bool,
+    /// Directories in `template` that are overlaid in order, like `common`
+    /// and then `gradle` if `build_tool == gradle`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variants: Vec<VariantSpec>,
 }
 
-#[derive(Serialize, Deserialize)]
+impl BlueprintMetadata {
+    fn match_options(&self) -> MatchOptions {
+        MatchOptions {
+            case_sensitive: !self.case_insensitive_patterns,
+        }
+    }
+
+    /// Merges in the metadata of an extended blueprint. Its values, presets,
+    /// verbatim and ignored files and `include_if` rules come first, and the
+    /// ones declared here replace the parent's ones of the same name. Its
+    /// `git_init` setting applies unless this blueprint has its own.
+    ///
+    /// Upgrades aren't inherited, as their versions are the parent's
+    /// versions. Neither are the variants, which are directories of the
+    /// parent's template, nor the tag pattern of the parent's repository.
+    fn inherit(&mut self, parent: &BlueprintMetadata) {
+        let mut values: Vec<ValueSpec> = parent
+            .values
+            .iter()
+            .filter(|v| !self.values.iter().any(|own| own.name == v.name))
+            .cloned()
+            .collect();
+        values.append(&mut self.values);
+        self.values = values;
+
+        let mut presets = parent.presets.clone();
+        presets.extend(self.presets.drain(..));
+        self.presets = presets;
+
+        let mut verbatim = parent.verbatim.clone();
+        verbatim.append(&mut self.verbatim);
+        self.verbatim = verbatim;
+
+        let mut ignore = parent.ignore.clone();
+        ignore.append(&mut self.ignore);
+        self.ignore = ignore;
+
+        let mut include_if = parent.include_if.clone();
+        include_if.extend(self.include_if.drain(..));
+        self.include_if = include_if;
+
+        self.git_init = self.git_init.or(parent.git_init);
+    }
+
+    /// Fails if the blueprint can't be rendered by the given rendr version.
+    pub fn check_rendr_version(
+        &self,
+        current: &BlueprintVersion,
+    ) -> Result<(), BlueprintInitError> {
+        if let Some(required) = self.min_rendr_version.as_ref().filter(|v| current < v) {
+            return Err(BlueprintInitError::RendrTooOld {
+                required: required.clone(),
+                current: current.clone(),
+            });
+        }
+        if let Some(supported) = self.max_rendr_version.as_ref().filter(|v| current > v) {
+            return Err(BlueprintInitError::RendrTooNew {
+                supported: supported.clone(),
+                current: current.clone(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// The version of this rendr.
+pub fn rendr_version() -> BlueprintVersion {
+    env!("CARGO_PKG_VERSION")
+        .parse()
+        .expect("rendr's own version is a valid version")
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct ValueSpec {
     pub name: String,
     pub description: String,
-    pub default: Option<String>,
+    pub default: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_from_env: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_command: Option<String>,
     #[serde(default)]
     pub required: bool,
+    #[serde(default)]
+    pub secret: bool,
 }
 
-#[derive(Serialize, Deserialize)]
+impl ValueSpec {
+    /// The environment variable a secret value can be read from, e.g.
+    /// `API_TOKEN` for a value named `api_token`, or `DATABASE_PASSWORD` for
+    /// `database.password`.
+    pub fn env_var_name(&self) -> String {
+        values::env_var_name(&self.name)
+    }
+
+    pub fn from_env(&self) -> Option<String> {
+        std::env::var(self.env_var_name()).ok()
+    }
+
+    /// The default for this value. The `default_from_env` variable wins if it
+    /// is set, then the output of `default_command`, then the static
+    /// `default`. A failing command is treated as having no default.
+    pub fn default_value(&self, working_dir: &Path) -> Option<Value> {
+        if let Some(var) = &self.default_from_env {
+            match std::env::var(var) {
+                Ok(value) if !value.is_empty() => return Some(Value::from(value)),
+                _ => debug!("No default for `{}` in ${}", self.name, var),
+            }
+        }
+
+        if let Some(command) = &self.default_command {
+            match run_default_command(command, working_dir, DEFAULT_COMMAND_TIMEOUT) {
+                Ok(value) if !value.is_empty() => return Some(Value::from(value)),
+                Ok(_) => debug!("Default command for `{}` had no output", self.name),
+                Err(e) => debug!("Default command for `{}` failed: {}", self.name, e),
+            }
+        }
+
+        self.default.clone()
+    }
+}
+
+/// How long a `default_command` may run before it's killed.
+const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Runs a `default_command` in the working directory, returning its trimmed
+/// standard output.
+fn run_default_command(
+    command: &str,
+    working_dir: &Path,
+    timeout: Duration,
+) -> Result<String, DynError> {
+    debug!("Running default command: {}", command);
+
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(working_dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    // The output is read on its own thread, so that a command printing more
+    // than the pipe holds doesn't block while we wait for it to exit.
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = sender.send(stdout.read_to_end(&mut output).map(|_| output));
+    });
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if started.elapsed() >= timeout {
+            child.kill()?;
+            child.wait()?;
+            return Err(format!("timed out after {}s", timeout.as_secs()).into());
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    if !status.success() {
+        return Err(format!("exited with {}", status).into());
+    }
+
+    // Processes started by the command may still hold on to its output.
+    let output = receiver
+        .recv_timeout(timeout.saturating_sub(started.elapsed()))
+        .map_err(|_| format!("timed out after {}s", timeout.as_secs()))??;
+
+    Ok(String::from_utf8(output)?.trim().to_string())
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct VariantSpec {
+    pub root: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<Condition>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct UpgradeSpec {
-    pub version: u32,
+    pub version: BlueprintVersion,
+    /// Only run the script when upgrading from a version in this range.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<VersionRange>,
     pub script: String,
     pub executable: String,
 }
@@ -639,7 +1726,7 @@ mod tests {
         let blueprint = Blueprint::new("test_assets/example_blueprint", None).unwrap();
 
         assert_eq!(blueprint.metadata.name, "example-blueprint");
-        assert_eq!(blueprint.metadata.version, 1);
+        assert_eq!(blueprint.metadata.version, BlueprintVersion::from(1));
         assert_eq!(
             blueprint.metadata.author,
             "Brian S. <brian.stewart@jamf.com>, Tomasz K. <tomasz.kurcz@jamf.com>"
@@ -678,6 +1765,43 @@ mod tests {
         assert!(another_test.find("version: 1").is_some());
     }
 
+    #[test]
+    fn extended_blueprints_are_overlaid() {
+        let blueprint = Blueprint::new("test_assets/example_blueprint_extending", None).unwrap();
+        let output_dir = TempDir::new("my-project").unwrap();
+
+        let names: Vec<_> = blueprint.values().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, vec!["name", "extra_info", "version", "language"]);
+        assert!(blueprint.is_verbatim("excluded_file"));
+
+        let mut values = test_values();
+        values.insert("language", "rust");
+        blueprint
+            .render(
+                &Tmplpp::new(),
+                &values,
+                output_dir.path(),
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+
+        let test = fs::read_to_string(output_dir.path().join("test.yaml")).unwrap();
+        assert!(test.contains("language: rust"));
+        assert!(!test.contains("stuff: foobar"));
+        assert!(output_dir.path().join("another-test.yaml").exists());
+        assert!(output_dir.path().join("dir/test.yaml").exists());
+        assert!(!output_dir.path().join("excluded_files").exists());
+        assert!(!output_dir.path().join("context.txt").exists());
+        assert_eq!(
+            blueprint.template_path("new-file.txt"),
+            Path::new("test_assets/example_blueprint/template/new-file.txt")
+                .canonicalize()
+                .unwrap()
+        );
+    }
+
     #[test]
     fn render_example_blueprint_recursive() {
         let blueprint = Blueprint::new("test_assets/example_blueprint", None).unwrap();
@@ -703,6 +1827,329 @@ mod tests {
         assert!(test.find("version: 1").is_some());
     }
 
+    #[test]
+    fn context_values_are_available_to_templates() {
+        let blueprint = Blueprint::new("test_assets/example_blueprint", None).unwrap();
+
+        let output_dir = TempDir::new("my-project").unwrap();
+
+        let engine = Tmplpp::new();
+
+        blueprint
+            .render(
+                &engine,
+                &test_values(),
+                output_dir.path(),
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+
+        let context = fs::read_to_string(output_dir.path().join("context.txt")).unwrap();
+        let dir_name = output_dir.path().file_name().unwrap().to_str().unwrap();
+
+        assert!(context
+            .find(&format!("rendr {} from", env!("CARGO_PKG_VERSION")))
+            .is_some());
+        assert!(context.find("from example-blueprint v1.").is_some());
+        assert!(context
+            .find(&format!("Project: {} in {}", dir_name, dir_name))
+            .is_some());
+        assert!(context
+            .find(&Local::now().format("%Y").to_string())
+            .is_some());
+
+        // Only the time- and machine-dependent context values are stored.
+        let rendr_file = fs::read_to_string(output_dir.path().join(".rendr.yaml")).unwrap();
+        assert!(rendr_file.find("rendr:").is_none());
+        assert!(rendr_file.find("project:").is_none());
+        let config: RendrConfig = serde_yaml::from_str(&rendr_file).unwrap();
+        assert_eq!(
+            config.context.get("now.year"),
+            Some(&Local::now().format("%Y").to_string())
+        );
+    }
+
+    #[test]
+    fn presets_are_recorded_in_rendr_file() {
+        let blueprint = Blueprint::new("test_assets/example_blueprint", None).unwrap();
+
+        let preset = blueprint.preset("full").unwrap();
+        assert_eq!(preset.get("extra_info").unwrap(), "Everything included");
+        assert!(blueprint.preset("unknown").is_none());
+
+        let output_dir = TempDir::new("my-project").unwrap();
+        let mut values = test_values();
+        values.merge(preset);
+        values.insert(PRESET_VALUE, "full");
+
+        blueprint
+            .render(
+                &Tmplpp::new(),
+                &values,
+                output_dir.path(),
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+
+        let config = RendrConfig::load(&output_dir.path().join(".rendr.yaml"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(config.preset, Some(String::from("full")));
+    }
+
+    #[test]
+    fn upgrade_scripts_are_selected_by_version() {
+        let mut blueprint =
+            Blueprint::new("test_assets/example_blueprint_with_upgrade_script", None).unwrap();
+        blueprint.metadata.upgrades.push(UpgradeSpec {
+            version: "2.1.0".parse().unwrap(),
+            from: Some("^1".parse().unwrap()),
+            script: String::from("upgrade-from-v1.sh"),
+            executable: String::from("sh"),
+        });
+
+        let scripts = |from: &str, to: &str| -> Vec<String> {
+            blueprint
+                .get_upgrade_scripts(&from.parse().unwrap(), &to.parse().unwrap())
+                .iter()
+                .map(|s| s.script.clone())
+                .collect()
+        };
+
+        assert_eq!(
+            scripts("1", "3"),
+            vec![
+                "upgrade-v2.groovy",
+                "upgrade-from-v1.sh",
+                "upgrade-v3.groovy"
+            ]
+        );
+        assert_eq!(scripts("2.0.0", "3"), vec!["upgrade-v3.groovy"]);
+        assert_eq!(scripts("1", "2.0.5"), vec!["upgrade-v2.groovy"]);
+        assert!(scripts("3", "3").is_empty());
+    }
+
+    #[test]
+    fn addons_are_rendered_into_projects() {
+        let blueprint = Blueprint::new("test_assets/example_blueprint", None).unwrap();
+        let addon = Blueprint::new("test_assets/example_addon", None).unwrap();
+        let output_dir = TempDir::new("my-project").unwrap();
+        let engine = Tmplpp::new();
+
+        blueprint
+            .render(
+                &engine,
+                &test_values(),
+                output_dir.path(),
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+        let mut values = test_values();
+        values.insert("database", "orders");
+        addon
+            .render_addon(&engine, &values, output_dir.path(), false)
+            .unwrap();
+
+        let database = fs::read_to_string(output_dir.path().join("database.yaml")).unwrap();
+        assert!(database.contains("name: orders"));
+        assert!(database.contains("user: my-project"));
+        let test = fs::read_to_string(output_dir.path().join("test.yaml")).unwrap();
+        assert!(!test.contains("overwritten"));
+
+        let rendr_file = output_dir.path().join(".rendr.yaml");
+        let config = RendrConfig::load(&rendr_file).unwrap().unwrap();
+        let recorded = config.addon("example-addon").unwrap();
+        assert_eq!(recorded.version, BlueprintVersion::new(1, 0, 0));
+        let addon_values = recorded.values().decrypted(None).unwrap();
+        assert_eq!(addon_values.get("database").unwrap(), "orders");
+        assert!(!addon_values.contains("version"));
+
+        // Rendering the project's blueprint again keeps the add-on.
+        blueprint
+            .render(
+                &engine,
+                &test_values(),
+                output_dir.path(),
+                false,
+                true,
+                false,
+            )
+            .unwrap();
+        let config = RendrConfig::load(&rendr_file).unwrap().unwrap();
+        assert!(config.addon("example-addon").is_some());
+    }
+
+    #[test]
+    fn rendr_file_pins_the_compatible_version_range() {
+        let blueprint = Blueprint::new("test_assets/example_blueprint", None).unwrap();
+        let output_dir = TempDir::new("my-project").unwrap();
+
+        blueprint
+            .render(
+                &Tmplpp::new(),
+                &test_values(),
+                output_dir.path(),
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+
+        let config = RendrConfig::load(&output_dir.path().join(".rendr.yaml"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(config.version, BlueprintVersion::new(1, 0, 0));
+        let range = config.version_range.unwrap();
+        assert!(range.matches(&"1.4.2".parse().unwrap()));
+        assert!(!range.matches(&"2.0.0".parse().unwrap()));
+    }
+
+    #[test]
+    fn extended_blueprints_keep_their_own_upgrades() {
+        let blueprint =
+            Blueprint::new("test_assets/example_blueprint_extending_upgrades", None).unwrap();
+
+        // The parent's upgrades to its versions 2 and 3 don't apply.
+        let versions: Vec<_> = blueprint
+            .get_upgrade_scripts(&BlueprintVersion::new(1, 0, 0), &"3".parse().unwrap())
+            .into_iter()
+            .map(|u| u.version.to_string())
+            .collect();
+        assert_eq!(versions, vec!["1.1.0"]);
+
+        // The parent initializes a Git repository, but the blueprint doesn't.
+        assert!(!blueprint.is_git_init_enabled());
+        assert!(blueprint.is_ignored("notes.txt"));
+        assert!(blueprint.is_verbatim("excluded_file"));
+    }
+
+    #[test]
+    fn files_are_included_by_conditions() {
+        let blueprint =
+            Blueprint::new("test_assets/example_blueprint_with_conditions", None).unwrap();
+        let engine = Tmplpp::new();
+
+        let without_docker = TempDir::new("my-project").unwrap();
+        let mut values = test_values();
+        values.insert("use_docker", "false");
+        values.insert("ci_provider", "none");
+        blueprint
+            .render(&engine, &values, without_docker.path(), false, true, false)
+            .unwrap();
+        assert!(without_docker.path().join("README.md").exists());
+        assert!(!without_docker.path().join("docker").exists());
+        assert!(!without_docker.path().join("ci.yml").exists());
+
+        let with_docker = TempDir::new("my-project").unwrap();
+        values.insert("use_docker", "true");
+        values.insert("ci_provider", "github");
+        blueprint
+            .render(&engine, &values, with_docker.path(), false, true, false)
+            .unwrap();
+        assert!(with_docker.path().join("docker/Dockerfile").exists());
+        assert!(with_docker.path().join("ci.yml").exists());
+    }
+
+    #[test]
+    fn template_variants_are_chosen_by_values() {
+        let mut blueprint =
+            Blueprint::new("test_assets/example_blueprint_with_variants", None).unwrap();
+        let engine = Tmplpp::new();
+
+        let gradle = TempDir::new("my-project").unwrap();
+        let mut values = test_values();
+        values.insert("build_tool", "gradle");
+        blueprint
+            .render(&engine, &values, gradle.path(), false, true, false)
+            .unwrap();
+        let readme = fs::read_to_string(gradle.path().join("README.md")).unwrap();
+        assert!(readme.contains("Gradle"));
+        assert!(gradle.path().join("NOTICE.txt").exists());
+        assert!(gradle.path().join("settings.gradle").exists());
+        assert!(!gradle.path().join("pom.xml").exists());
+
+        let config = RendrConfig::load(&gradle.path().join(".rendr.yaml"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            config.variants,
+            Some(vec!["common".into(), "gradle".into()])
+        );
+
+        let maven = TempDir::new("my-project").unwrap();
+        values.insert("build_tool", "maven");
+        blueprint
+            .render(&engine, &values, maven.path(), false, true, false)
+            .unwrap();
+        let readme = fs::read_to_string(maven.path().join("README.md")).unwrap();
+        assert!(readme.contains("default build tool"));
+        assert!(maven.path().join("pom.xml").exists());
+        assert!(!maven.path().join("settings.gradle").exists());
+
+        // Recorded variants win over the values.
+        blueprint.variants = config.variants;
+        let files: Vec<_> = blueprint
+            .included_files(&values)
+            .map(|f| f.unwrap().path_from_template_root().to_path_buf())
+            .collect();
+        assert!(files.contains(&PathBuf::from("settings.gradle")));
+        assert!(!files.contains(&PathBuf::from("pom.xml")));
+        assert_eq!(
+            files
+                .iter()
+                .filter(|f| **f == Path::new("README.md"))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn context_value_names_are_reserved() {
+        match Blueprint::new("test_assets/example_blueprint_with_reserved_value", None) {
+            Err(BlueprintInitError::ReservedValueName(name)) => {
+                assert_eq!(name, "rendr.version")
+            }
+            _ => panic!("expected a reserved value name error"),
+        }
+    }
+
+    #[test]
+    fn blueprints_can_require_a_rendr_version() {
+        match Blueprint::new("test_assets/example_blueprint_requiring_newer_rendr", None) {
+            Err(BlueprintInitError::RendrTooOld { required, current }) => {
+                assert_eq!(required, BlueprintVersion::new(999, 0, 0));
+                assert_eq!(current, rendr_version());
+            }
+            _ => panic!("expected a rendr version error"),
+        }
+
+        let mut metadata = Blueprint::new("test_assets/example_blueprint", None)
+            .unwrap()
+            .metadata;
+        metadata.min_rendr_version = Some("1.1".parse().unwrap());
+        metadata.max_rendr_version = Some("1.2".parse().unwrap());
+        assert!(metadata
+            .check_rendr_version(&"1.1.0".parse().unwrap())
+            .is_ok());
+        assert!(metadata
+            .check_rendr_version(&"1.2.0".parse().unwrap())
+            .is_ok());
+        assert!(matches!(
+            metadata.check_rendr_version(&"1.0.9".parse().unwrap()),
+            Err(BlueprintInitError::RendrTooOld { .. })
+        ));
+        assert!(matches!(
+            metadata.check_rendr_version(&"1.3.0".parse().unwrap()),
+            Err(BlueprintInitError::RendrTooNew { .. })
+        ));
+    }
+
     #[test]
     fn exclusions_work() {
         let blueprint = Blueprint::new("test_assets/example_blueprint", None).unwrap();
@@ -727,6 +2174,35 @@ mod tests {
         assert!(excluded_file.find("{{ name }}").is_some());
     }
 
+    #[test]
+    fn ignored_files_are_never_rendered() {
+        let blueprint =
+            Blueprint::new("test_assets/example_blueprint_with_ignored_files", None).unwrap();
+        let output_dir = TempDir::new("my-project").unwrap();
+
+        blueprint
+            .render(
+                &Tmplpp::new(),
+                &test_values(),
+                output_dir.path(),
+                false,
+                true,
+                false,
+            )
+            .unwrap();
+
+        assert!(output_dir.path().join("README.md").exists());
+        assert!(!output_dir.path().join("NOTES.md").exists());
+        assert!(!output_dir.path().join("fixtures").exists());
+        let raw = fs::read_to_string(output_dir.path().join("raw.txt")).unwrap();
+        assert!(raw.contains("{{ name }}"));
+
+        let patterns = parse_ignore_file("# comment\nfixtures/\n\n!keep.json\n").unwrap();
+        assert_eq!(patterns.len(), 2);
+        assert!(patterns[1].is_negated());
+        assert!(parse_ignore_file("/").is_err());
+    }
+
     #[test]
     fn glob_exclusions_work() {
         let blueprint = Blueprint::new("test_assets/example_blueprint", None).unwrap();
@@ -762,7 +2238,7 @@ mod tests {
             PathBuf::from("test_assets/scripts/hello_world.sh"),
         );
 
-        script.run(Path::new("."), &Values::new()).unwrap();
+        script.run(Path::new("."), &Values::new(), &[]).unwrap();
     }
 
     #[test]
@@ -772,7 +2248,7 @@ mod tests {
             PathBuf::from("test_assets/scripts/failing.sh"),
         );
 
-        if let Ok(()) = script.run(Path::new("."), &Values::new()) {
+        if let Ok(()) = script.run(Path::new("."), &Values::new(), &[]) {
             panic!("The failing script didn't cause an error!");
         }
     }
@@ -801,6 +2277,129 @@ mod tests {
         assert_eq!(script_output.as_str(), "something123");
     }
 
+    #[test]
+    fn rendr_file_is_reproducible() {
+        let blueprint = Blueprint::new("test_assets/example_blueprint", None).unwrap();
+        let engine = Tmplpp::new();
+
+        let mut rendr_files = Vec::new();
+        for values in vec![
+            vec![
+                ("foobar", "stuff"),
+                ("version", "1"),
+                ("name", "my-project"),
+            ],
+            vec![
+                ("name", "my-project"),
+                ("foobar", "stuff"),
+                ("version", "1"),
+            ],
+        ] {
+            let output_dir = TempDir::new("my-project").unwrap();
+            let values: Values = values.into_iter().collect();
+
+            blueprint
+                .render(&engine, &values, output_dir.path(), false, true, false)
+                .unwrap();
+
+            rendr_files.push(fs::read_to_string(output_dir.path().join(".rendr.yaml")).unwrap());
+        }
+
+        assert_eq!(rendr_files[0], rendr_files[1]);
+        assert!(rendr_files[0]
+            .find("values:\n  name: my-project\n  version: \"1\"\n  foobar: stuff")
+            .is_some());
+    }
+
+    #[test]
+    fn secret_values_are_not_written_to_rendr_file() {
+        let blueprint = Blueprint::new("test_assets/example_blueprint_with_secrets", None).unwrap();
+
+        let output_dir = TempDir::new("my-project").unwrap();
+
+        let engine = Tmplpp::new();
+
+        blueprint
+            .render(
+                &engine,
+                &secret_test_values(),
+                output_dir.path(),
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+
+        let config = fs::read_to_string(output_dir.path().join("config.yaml")).unwrap();
+        let rendr_file = fs::read_to_string(output_dir.path().join(".rendr.yaml")).unwrap();
+        let script_output = fs::read_to_string(output_dir.path().join("script_output.md")).unwrap();
+
+        assert!(config.find("token: hunter2").is_some());
+        assert!(rendr_file.find("name: my-project").is_some());
+        assert!(rendr_file.find("api_token").is_none());
+        assert!(rendr_file.find("hunter2").is_none());
+        assert_eq!(script_output.as_str(), "hunter2");
+    }
+
+    #[test]
+    fn secret_values_are_completed_from_the_environment() {
+        let mut blueprint =
+            Blueprint::new("test_assets/example_blueprint_with_secrets", None).unwrap();
+        blueprint.metadata.values[1].name = String::from("rendr_test_api_token");
+        std::env::set_var("RENDR_TEST_API_TOKEN", "from-env");
+
+        let mut provided = Values::new();
+        provided.insert("name", "my-project");
+        let values = blueprint.complete_values(Path::new("."), provided).unwrap();
+        std::env::remove_var("RENDR_TEST_API_TOKEN");
+
+        assert_eq!(
+            values.get("rendr_test_api_token"),
+            Some(&String::from("from-env"))
+        );
+    }
+
+    #[test]
+    fn missing_secrets_are_an_error_without_prompts() {
+        let mut blueprint =
+            Blueprint::new("test_assets/example_blueprint_with_secrets", None).unwrap();
+        blueprint.metadata.values[1].name = String::from("rendr_test_missing_secret");
+
+        let mut provided = Values::new();
+        provided.insert("name", "my-project");
+        let error = blueprint
+            .complete_values_without_prompts(Path::new("."), provided)
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "secret `rendr_test_missing_secret` not provided; \
+             set RENDR_TEST_MISSING_SECRET or pass -v rendr_test_missing_secret:<value>"
+        );
+    }
+
+    #[test]
+    fn secret_values_are_not_passed_as_script_flags() {
+        let output_dir = TempDir::new("my-project").unwrap();
+
+        let mut script = Script::new(
+            "some script",
+            PathBuf::from("test_assets/scripts/print_args.sh")
+                .canonicalize()
+                .unwrap(),
+        );
+        script.executable = Some(String::from("sh"));
+
+        script
+            .run(output_dir.path(), &secret_test_values(), &["api_token"])
+            .unwrap();
+
+        let script_args = fs::read_to_string(output_dir.path().join("script_args.txt")).unwrap();
+
+        assert!(script_args.find("--value name=my-project").is_some());
+        assert!(script_args.find("hunter2").is_none());
+    }
+
     #[test]
     fn git_init_works() -> Result<(), Box<dyn Error>> {
         use git2::RepositoryState;
@@ -832,6 +2431,58 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn defaults_can_come_from_commands_and_the_environment() {
+        let specs: Vec<ValueSpec> = serde_yaml::from_str(
+            r#"
+- name: from_command
+  description: A value from a command
+  default_command: echo "  hello  "
+- name: failing_command
+  description: A value from a failing command
+  default_command: exit 1
+  default: fallback
+- name: from_env
+  description: A value from the environment
+  default_from_env: RENDR_TEST_DEFAULT_FROM_ENV
+  default_command: echo ignored
+- name: missing_env
+  description: A value from a missing environment variable
+  default_from_env: RENDR_TEST_DEFAULT_MISSING
+"#,
+        )
+        .unwrap();
+        std::env::set_var("RENDR_TEST_DEFAULT_FROM_ENV", "from-env");
+        let dir = Path::new(".");
+
+        assert_eq!(specs[0].default_value(dir), Some(Value::from("hello")));
+        assert_eq!(specs[1].default_value(dir), Some(Value::from("fallback")));
+        assert_eq!(specs[2].default_value(dir), Some(Value::from("from-env")));
+        assert_eq!(specs[3].default_value(dir), None);
+
+        let yaml = serde_yaml::to_string(&specs[0]).unwrap();
+        assert!(yaml.find("default_from_env").is_none());
+    }
+
+    #[test]
+    fn default_commands_time_out() {
+        let result = run_default_command("sleep 5", Path::new("."), Duration::from_millis(50));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn default_commands_can_print_a_lot() {
+        let output = run_default_command(
+            "head -c 200000 /dev/zero | tr '\\0' a",
+            Path::new("."),
+            Duration::from_secs(5),
+        )
+        .unwrap();
+
+        assert_eq!(output.len(), 200000);
+    }
+
     // Test helpers
     fn test_values() -> Values {
         vec![
@@ -844,4 +2495,12 @@ mod tests {
         .collect::<HashMap<_, _>>()
         .into()
     }
+
+    fn secret_test_values() -> Values {
+        vec![("name", "my-project"), ("api_token", "hunter2")]
+            .iter()
+            .cloned()
+            .collect::<HashMap<_, _>>()
+            .into()
+    }
 }
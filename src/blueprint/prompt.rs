@@ -0,0 +1,63 @@
+//! Interactive prompts for values that weren't provided otherwise.
+
+use std::io::{self, Write};
+
+use text_io::try_read;
+use thiserror::Error;
+
+use super::ValueSpec;
+
+type ValueFromPrompt<'s> = (&'s str, String);
+
+/// Prompts for each of the values in turn. Secret values aren't echoed.
+pub fn prompt_for_values<'s>(
+    values: impl Iterator<Item = &'s ValueSpec>,
+) -> impl Iterator<Item = Result<ValueFromPrompt<'s>, PromptError>> {
+    values.map(prompt_for_value)
+}
+
+fn prompt_for_value(value: &ValueSpec) -> Result<ValueFromPrompt<'_>, PromptError> {
+    if value.secret {
+        let prompt = format!("{}: ", value.description);
+        let secret = rpassword::read_password_from_tty(Some(&prompt))
+            .map_err(|_| PromptError::not_provided(value))?;
+        return Ok((&value.name, secret));
+    }
+
+    print!("{}: ", value.description);
+    io::stdout()
+        .flush()
+        .map_err(|_| PromptError::not_provided(value))?;
+    let answer: String = try_read!("{}\n").map_err(|_| PromptError::not_provided(value))?;
+    Ok((&value.name, answer))
+}
+
+/// The secret values that are set in the environment.
+pub fn secret_values_from_env<'s>(
+    values: impl Iterator<Item = &'s ValueSpec>,
+) -> impl Iterator<Item = ValueFromPrompt<'s>> {
+    values.filter_map(|v| v.from_env().map(|value| (v.name.as_str(), value)))
+}
+
+#[derive(Error, Debug)]
+pub enum PromptError {
+    #[error("secret `{name}` not provided; set {env_var} or pass -v {name}:<value>")]
+    SecretNotProvided { name: String, env_var: String },
+
+    #[error("value `{0}` not provided; pass -v {0}:<value>")]
+    ValueNotProvided(String),
+}
+
+impl PromptError {
+    /// The error for a value that's missing and couldn't be asked for.
+    pub fn not_provided(value: &ValueSpec) -> Self {
+        if value.secret {
+            PromptError::SecretNotProvided {
+                name: value.name.clone(),
+                env_var: value.env_var_name(),
+            }
+        } else {
+            PromptError::ValueNotProvided(value.name.clone())
+        }
+    }
+}
@@ -1,35 +1,106 @@
-use std::error::Error;
-use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 
-use git2::{Cred, RemoteCallbacks};
-use log::{debug, error};
+use git2::build::CheckoutBuilder;
+use git2::{Oid, RemoteCallbacks, Repository};
+use log::debug;
 use tempdir::TempDir;
-use text_io::read;
 use thiserror::Error;
 
+use super::archive::{self, ArchiveKind};
+use super::cache::Cache;
+use super::credentials::Credentials;
+use super::download;
 use super::BlueprintAuth;
-
-type DynError = Box<dyn Error>;
+use crate::config::{ConfigError, UserConfig};
 
 pub enum Source {
     Local(PathBuf),
+    Archive(ArchiveSource),
+    Http(HttpSource),
     Remote(RemoteSource),
 }
 
 impl Source {
+    /// Initializes a blueprint source from a local path or a git URL. A git
+    /// URL may have a `//path` suffix naming the blueprint's directory within
+    /// the repository, and a `#ref` suffix naming the branch, tag or commit to
+    /// check out, e.g. `https://github.com/your/blueprints//service#v2`.
+    ///
+    /// HTTP(S) URLs of `.tar.gz`, `.tgz` or `.zip` files are downloaded and
+    /// extracted. They may have a `#sha256=<checksum>` suffix, in which case
+    /// the download must match the checksum.
+    ///
+    /// The source may also start with an alias from the user config, which is
+    /// replaced by the source it stands for, e.g. `rust-service#v2`.
     pub fn new(source: &str, auth: Option<BlueprintAuth>) -> Result<Self, BlueprintSourceError> {
+        Self::with_cache(source, auth, &Cache::default())
+    }
+
+    /// Like `new`, but remote repositories are fetched into the given cache.
+    pub fn with_cache(
+        source: &str,
+        auth: Option<BlueprintAuth>,
+        cache: &Cache,
+    ) -> Result<Self, BlueprintSourceError> {
+        let config = match Path::new(source).exists() {
+            true => UserConfig::default(),
+            false => UserConfig::load()?,
+        };
+
+        Self::with_config(source, auth, cache, &config)
+    }
+
+    /// Like `with_cache`, but with the given user config. Aliases and URL
+    /// rewrites come from it.
+    fn with_config(
+        source: &str,
+        auth: Option<BlueprintAuth>,
+        cache: &Cache,
+        config: &UserConfig,
+    ) -> Result<Self, BlueprintSourceError> {
+        let source = resolve_alias(source, config);
+        let source = source.as_str();
+
         let path = Path::new(source);
         debug!("Initializing blueprint source from {}", path.display());
 
         if path.exists() {
+            if let (true, Some(kind)) = (path.is_file(), ArchiveKind::of(source)) {
+                debug!("Source path is an archive, extracting");
+                return Self::archive(path, kind);
+            }
+
             debug!("Source path exists, loading");
             return Ok(Self::local(path)?);
         }
 
+        let (url, git_ref) = match source.rsplit_once('#') {
+            Some((url, git_ref)) if !git_ref.is_empty() => (url, Some(git_ref)),
+            _ => (source, None),
+        };
+
+        if download::is_http(url) {
+            let path = url.split('?').next().unwrap_or(url);
+            if let Some(kind) = ArchiveKind::of(path) {
+                debug!("Source is an archive URL, downloading");
+                let token = download::token_from_env();
+                return Self::http(
+                    url,
+                    &config.rewrite(url),
+                    kind,
+                    git_ref,
+                    cache,
+                    token.as_deref(),
+                );
+            }
+        }
+
+        let (url, subdir) = split_subdir(url);
+
         debug!("Source path does not exist, loading from remote source");
         let callbacks = Source::prepare_callbacks(auth);
-        Self::remote(source, Some(callbacks))
+        let fetch_url = config.rewrite(url);
+        Self::remote(url, &fetch_url, subdir, git_ref, cache, Some(callbacks))
     }
 
     fn local(path: impl AsRef<Path>) -> Result<Self, BlueprintSourceError> {
@@ -40,28 +111,116 @@ impl Source {
         ))
     }
 
-    fn remote(url: &str, callbacks: Option<RemoteCallbacks>) -> Result<Self, BlueprintSourceError> {
-        let dir = TempDir::new("checked_out_blueprint")
-            .map_err(|e| BlueprintSourceError::TempDirCreationError(e))?;
+    fn archive(path: &Path, kind: ArchiveKind) -> Result<Self, BlueprintSourceError> {
+        let archive = path
+            .canonicalize()
+            .map_err(BlueprintSourceError::LocalReadError)?;
 
-        // Prepare fetch options.
-        let mut fo = git2::FetchOptions::new();
+        Ok(Source::Archive(ArchiveSource::extract(archive, kind)?))
+    }
 
-        // Set callbacks if provided.
-        if let Some(callbacks) = callbacks {
-            fo.remote_callbacks(callbacks);
+    /// Downloads the archive at `fetch_url`, which is where `url` is
+    /// fetched from after applying the URL rewrites. An archive with an
+    /// expected checksum is only downloaded if it isn't cached yet.
+    fn http(
+        url: &str,
+        fetch_url: &str,
+        kind: ArchiveKind,
+        fragment: Option<&str>,
+        cache: &Cache,
+        token: Option<&str>,
+    ) -> Result<Self, BlueprintSourceError> {
+        let expected = match fragment {
+            Some(fragment) => Some(
+                fragment
+                    .strip_prefix("sha256=")
+                    .ok_or_else(|| BlueprintSourceError::InvalidChecksum(fragment.to_string()))?
+                    .to_lowercase(),
+            ),
+            None => None,
+        };
+
+        if let Some(expected) = &expected {
+            if let Some(archive) = cache.archive(expected, kind.file_name())? {
+                return Ok(Source::Http(HttpSource {
+                    url: url.to_string(),
+                    sha256: expected.clone(),
+                    archive: ArchiveSource::extract(archive, kind)?,
+                }));
+            }
+        }
+
+        if cache.offline() {
+            return Err(BlueprintSourceError::NotCached(url.to_string()));
+        }
+
+        let dir = cache.download_dir()?;
+        let file = dir.path().join(kind.file_name());
+        download::download(fetch_url, &file, cache.ca_bundle(), token)?;
+
+        let sha256 = download::sha256(&file)?;
+        if let Some(expected) = expected {
+            if expected != sha256 {
+                return Err(BlueprintSourceError::ChecksumMismatch {
+                    expected,
+                    actual: sha256,
+                });
+            }
         }
+        let archive = cache.store_archive(url, &file, &sha256)?;
 
-        // Prepare builder.
-        let mut builder = git2::build::RepoBuilder::new();
-        builder.fetch_options(fo);
+        Ok(Source::Http(HttpSource {
+            url: url.to_string(),
+            sha256,
+            archive: ArchiveSource::extract(archive, kind)?,
+        }))
+    }
 
-        // Clone the project.
-        builder.clone(url, dir.as_ref())?;
+    /// Checks out the repository at `fetch_url`, which is where `url` is
+    /// fetched from after applying the URL rewrites.
+    fn remote(
+        url: &str,
+        fetch_url: &str,
+        subdir: Option<&str>,
+        git_ref: Option<&str>,
+        cache: &Cache,
+        callbacks: Option<RemoteCallbacks>,
+    ) -> Result<Self, BlueprintSourceError> {
+        let dir = TempDir::new("checked_out_blueprint")
+            .map_err(|e| BlueprintSourceError::TempDirCreationError(e))?;
+
+        // Bring the cached repository up to date.
+        if fetch_url != url {
+            debug!("Fetching {} from {}", url, fetch_url);
+        }
+        let repo = cache.fetch(fetch_url, callbacks)?;
+
+        // Check out the requested ref, or the default branch.
+        let git_ref = match git_ref {
+            Some(git_ref) => git_ref.to_string(),
+            None => default_branch(&repo)?,
+        };
+        let commit = checkout(&repo, &git_ref, dir.path())?;
+        debug!("Checked out blueprint at {} ({})", git_ref, commit);
+
+        let path = match subdir {
+            Some(subdir) => dir.path().join(subdir),
+            None => dir.path().to_path_buf(),
+        };
+        if !path.is_dir() {
+            return Err(BlueprintSourceError::PathNotFound(
+                subdir.unwrap_or_default().to_string(),
+            ));
+        }
 
         Ok(Source::Remote(RemoteSource {
             url: url.to_string(),
-            checked_out: dir,
+            subdir: subdir.map(str::to_string),
+            git_ref,
+            commit,
+            path,
+            repo_path: repo.path().to_path_buf(),
+            _checked_out: dir,
         }))
     }
 
@@ -71,6 +230,8 @@ impl Source {
 
         match self {
             Remote(tmpdir) => tmpdir.path(),
+            Archive(src) => &src.path,
+            Http(src) => &src.archive.path,
             Local(path) => &path,
         }
     }
@@ -83,109 +244,241 @@ impl Source {
         let from = from.as_ref().canonicalize().unwrap();
 
         match self {
-            Local(path) => pathdiff::diff_paths(path, from)
-                .unwrap()
-                .into_os_string()
-                .into_string()
-                .unwrap(),
+            Local(path) | Archive(ArchiveSource { archive: path, .. }) => {
+                pathdiff::diff_paths(path, from)
+                    .unwrap()
+                    .into_os_string()
+                    .into_string()
+                    .unwrap()
+            }
+            Http(src) => src.url.clone(),
             Remote(src) => src.url().to_string(),
         }
     }
 
-    pub fn prepare_callbacks<'c>(auth: Option<BlueprintAuth>) -> RemoteCallbacks<'c> {
-        if auth.is_none() {
-            return RemoteCallbacks::new();
+    /// The SHA-256 checksum of a downloaded archive.
+    pub fn sha256(&self) -> Option<&str> {
+        match self {
+            Source::Http(src) => Some(&src.sha256),
+            _ => None,
         }
+    }
 
-        let auth = auth.unwrap();
+    /// The blueprint's directory within the repository, for remote sources
+    /// that don't have the blueprint at their root.
+    pub fn subdir(&self) -> Option<&str> {
+        match self {
+            Source::Remote(src) => src.subdir.as_deref(),
+            _ => None,
+        }
+    }
 
-        let provided_user = auth.user;
-        let provided_pass = auth.password;
-        let provided_ssh_key = auth.ssh_key;
+    /// The branch, tag or commit that was checked out, for remote sources.
+    pub fn git_ref(&self) -> Option<&str> {
+        match self {
+            Source::Remote(src) => Some(&src.git_ref),
+            _ => None,
+        }
+    }
 
-        let mut callbacks = RemoteCallbacks::new();
-        let mut auth_retries = 3;
+    /// The commit that was checked out, for remote sources.
+    pub fn commit(&self) -> Option<String> {
+        match self {
+            Source::Remote(src) => Some(src.commit.to_string()),
+            _ => None,
+        }
+    }
 
-        callbacks.credentials(move |_url, username_from_url, allowed_types| {
-            debug!("Git requested cred types: {:?}", allowed_types);
+    /// All tags of a remote source.
+    pub fn tags(&self) -> Result<Vec<String>, BlueprintSourceError> {
+        let src = match self {
+            Source::Remote(src) => src,
+            _ => return Ok(Vec::new()),
+        };
 
-            if auth_retries < 1 {
-                panic!("exceeded 3 auth retries; invalid credentials?");
-            }
+        let repo = Repository::open_bare(&src.repo_path)?;
+        let tags = repo.tag_names(None)?;
 
-            if allowed_types.is_ssh_key() {
-                auth_retries -= 1;
-
-                if let Some(ssh_key) = &provided_ssh_key {
-                    let path = Path::new(ssh_key.as_str());
-                    return Cred::ssh_key(
-                        &get_username(&provided_user, username_from_url).unwrap(),
-                        None,
-                        path,
-                        None,
-                    );
-                } else {
-                    return Cred::ssh_key(
-                        &get_username(&provided_user, username_from_url).unwrap(),
-                        None,
-                        &Path::new(&format!("{}/.ssh/id_rsa", std::env::var("HOME").unwrap())),
-                        None,
-                    );
-                }
-            } else if allowed_types.is_username() {
-                return Cred::username(&get_username(&provided_user, username_from_url).unwrap());
-            } else if allowed_types.is_user_pass_plaintext() {
-                auth_retries -= 1;
-
-                return Cred::userpass_plaintext(
-                    &get_username(&provided_user, username_from_url).unwrap(),
-                    &get_password(&provided_pass).unwrap(),
-                );
-            }
+        Ok(tags.iter().flatten().map(str::to_string).collect())
+    }
 
-            panic!(
-                "git requested an unimplemented credential type: {:?}",
-                allowed_types
-            )
+    /// The most recent tag of a remote source, by commit time.
+    pub fn latest_tag(&self) -> Result<Option<String>, BlueprintSourceError> {
+        let src = match self {
+            Source::Remote(src) => src,
+            _ => return Ok(None),
+        };
+
+        let repo = Repository::open_bare(&src.repo_path)?;
+        let mut tags = Vec::new();
+        for tag in repo.tag_names(None)?.iter().flatten() {
+            let time = repo
+                .revparse_single(tag)?
+                .peel_to_commit()?
+                .time()
+                .seconds();
+            tags.push((time, tag.to_string()));
+        }
+        let latest = tags.into_iter().max_by_key(|(time, _)| *time);
+
+        Ok(latest.map(|(_, tag)| tag))
+    }
+
+    pub fn prepare_callbacks<'c>(auth: Option<BlueprintAuth>) -> RemoteCallbacks<'c> {
+        let mut credentials = Credentials::new(auth);
+
+        let mut callbacks = RemoteCallbacks::new();
+        callbacks.credentials(move |url, username_from_url, allowed_types| {
+            credentials.next(url, username_from_url, allowed_types)
         });
 
-        fn get_username(
-            provided_user: &Option<String>,
-            username_from_url: Option<&str>,
-        ) -> Result<String, DynError> {
-            if let Some(username) = provided_user {
-                return Ok(username.to_string());
-            }
+        callbacks
+    }
+}
 
-            if let Some(username) = username_from_url {
-                return Ok(username.to_string());
-            }
+/// Builds a blueprint locator that `Source::new` understands from a source,
+/// an optional directory within it and an optional git ref.
+pub fn locator(source: &str, path: Option<&str>, git_ref: Option<&str>) -> String {
+    let mut locator = source.to_string();
 
-            print!("Username: ");
-            io::stdout().flush().unwrap();
-            Ok(read!("{}\n"))
+    if let Some(path) = path {
+        match Path::new(source).exists() {
+            true => locator = Path::new(source).join(path).display().to_string(),
+            false => locator = format!("{}//{}", locator, path.trim_start_matches('/')),
         }
+    }
+    if let Some(git_ref) = git_ref {
+        locator = format!("{}#{}", locator, git_ref);
+    }
 
-        fn get_password(provided_pass: &Option<String>) -> Result<String, DynError> {
-            if let Some(pass) = provided_pass {
-                return Ok(pass.to_string());
-            }
+    locator
+}
 
-            Ok(rpassword::read_password_from_tty(Some("Password: "))?)
+/// Replaces an alias at the start of the source with the source it stands
+/// for. A `//path` following the alias is appended to the aliased source's
+/// path, and a `#ref` replaces the aliased source's ref.
+fn resolve_alias(source: &str, config: &UserConfig) -> String {
+    let end = [source.find("//"), source.find('#')]
+        .iter()
+        .flatten()
+        .min()
+        .copied()
+        .unwrap_or(source.len());
+    let (name, rest) = source.split_at(end);
+
+    let target = match config.alias(name) {
+        Some(target) => target,
+        None => return source.to_string(),
+    };
+    debug!("Resolved alias {} to {}", name, target);
+
+    let (target, target_ref) = match target.rsplit_once('#') {
+        Some((target, git_ref)) => (target, Some(git_ref)),
+        None => (target, None),
+    };
+    let (path, git_ref) = match rest.split_once('#') {
+        Some((path, git_ref)) => (path, Some(git_ref)),
+        None => (rest, target_ref),
+    };
+    let path = path.strip_prefix("//").filter(|p| !p.is_empty());
+
+    match (split_subdir(target), path) {
+        ((url, Some(subdir)), Some(path)) => {
+            let subdir = format!("{}/{}", subdir.trim_end_matches('/'), path);
+            locator(url, Some(&subdir), git_ref)
         }
+        _ => locator(target, path, git_ref),
+    }
+}
 
-        callbacks
+/// Splits the `//path` suffix off a git URL. The `//` following the URL
+/// scheme doesn't count.
+fn split_subdir(url: &str) -> (&str, Option<&str>) {
+    let start = url.find("://").map_or(0, |i| i + 3);
+
+    match url[start..].find("//") {
+        Some(i) => {
+            let (url, subdir) = url.split_at(start + i);
+            let subdir = subdir.trim_matches('/');
+            (url, Some(subdir).filter(|s| !s.is_empty()))
+        }
+        None => (url, None),
     }
 }
 
+/// The name of the default branch of a cached repository.
+fn default_branch(repo: &Repository) -> Result<String, BlueprintSourceError> {
+    let head = repo
+        .find_reference("refs/remotes/origin/HEAD")
+        .and_then(|r| r.resolve())
+        .map_err(|_| BlueprintSourceError::RefNotFound(String::from("HEAD")))?;
+
+    Ok(head
+        .shorthand()
+        .map(|b| b.trim_start_matches("origin/"))
+        .unwrap_or("HEAD")
+        .to_string())
+}
+
+/// Checks out a branch, tag or commit of a cached repository into the target
+/// directory. Branches are looked up on the remote first, since the cache
+/// doesn't have any local branches.
+fn checkout(repo: &Repository, git_ref: &str, target: &Path) -> Result<Oid, BlueprintSourceError> {
+    let object = repo
+        .revparse_single(&format!("origin/{}", git_ref))
+        .or_else(|_| repo.revparse_single(git_ref))
+        .map_err(|_| BlueprintSourceError::RefNotFound(git_ref.to_string()))?;
+    let commit = object.peel_to_commit()?;
+
+    let mut checkout = CheckoutBuilder::new();
+    checkout.target_dir(target).update_index(false).force();
+    repo.checkout_tree(commit.as_object(), Some(&mut checkout))?;
+
+    Ok(commit.id())
+}
+
+pub struct ArchiveSource {
+    archive: PathBuf,
+    path: PathBuf,
+    // Holds on to the extracted files for as long as the source lives.
+    _extracted: TempDir,
+}
+
+impl ArchiveSource {
+    fn extract(archive: PathBuf, kind: ArchiveKind) -> Result<Self, BlueprintSourceError> {
+        let dir = TempDir::new("extracted_blueprint")
+            .map_err(BlueprintSourceError::TempDirCreationError)?;
+
+        let path = archive::extract(&archive, kind, dir.path())?;
+
+        Ok(ArchiveSource {
+            archive,
+            path,
+            _extracted: dir,
+        })
+    }
+}
+
+pub struct HttpSource {
+    url: String,
+    sha256: String,
+    archive: ArchiveSource,
+}
+
 pub struct RemoteSource {
     url: String,
-    checked_out: TempDir,
+    subdir: Option<String>,
+    git_ref: String,
+    commit: Oid,
+    path: PathBuf,
+    repo_path: PathBuf,
+    // Holds on to the checked out files for as long as the source lives.
+    _checked_out: TempDir,
 }
 
 impl RemoteSource {
     fn path(&self) -> &Path {
-        self.checked_out.path()
+        &self.path
     }
 
     fn url(&self) -> &str {
@@ -203,6 +496,54 @@ pub enum BlueprintSourceError {
 
     #[error("failed to clone the git repository")]
     RepoCloneError(#[from] git2::Error),
+
+    #[error("failed to authenticate to {url}: {reason}")]
+    AuthenticationFailed { url: String, reason: String },
+
+    #[error("the host key for {host} ({fingerprint}) is not in known_hosts; add it, or pass --accept-new-host-key to trust it on first use")]
+    UnknownHostKey { host: String, fingerprint: String },
+
+    #[error("the host key for {host} ({fingerprint}) doesn't match the one in known_hosts; the key may have changed, or the connection may be intercepted")]
+    HostKeyMismatch { host: String, fingerprint: String },
+
+    #[error("the host key for {host} ({fingerprint}) has been revoked")]
+    RevokedHostKey { host: String, fingerprint: String },
+
+    #[error("failed to verify the host key: {0}")]
+    HostKeyError(String),
+
+    #[error("failed to load the CA bundle {path}: {reason}")]
+    CaBundleError { path: PathBuf, reason: String },
+
+    #[error("no branch, tag or commit named `{0}` in the git repository")]
+    RefNotFound(String),
+
+    #[error("no directory `{0}` in the git repository")]
+    PathNotFound(String),
+
+    #[error("failed to extract the blueprint archive: {0}")]
+    ArchiveError(String),
+
+    #[error("refusing to extract unsafe archive entry `{0}`")]
+    UnsafeArchiveEntry(String),
+
+    #[error("failed to download the blueprint: {0}")]
+    DownloadError(String),
+
+    #[error("unsupported URL fragment `{0}`, expected `sha256=<checksum>`")]
+    InvalidChecksum(String),
+
+    #[error("the blueprint's checksum is {actual}, but {expected} was expected")]
+    ChecksumMismatch { expected: String, actual: String },
+
+    #[error("failed to access the blueprint cache")]
+    CacheError(#[source] std::io::Error),
+
+    #[error("{0} is not cached yet, so it can't be used offline")]
+    NotCached(String),
+
+    #[error("failed to load the user config")]
+    ConfigError(#[from] ConfigError),
 }
 
 #[test]
@@ -222,3 +563,301 @@ fn source_calculates_relative_path_correctly() {
         "test_assets/example_blueprint"
     );
 }
+
+#[cfg(test)]
+fn commit_file(repo: &Repository, name: &str, contents: &str) -> Oid {
+    use git2::{IndexAddOption, Signature};
+
+    std::fs::write(repo.workdir().unwrap().join(name), contents).unwrap();
+
+    let mut index = repo.index().unwrap();
+    index
+        .add_all(["*"].iter(), IndexAddOption::DEFAULT, None)
+        .unwrap();
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let sig = Signature::now("rendr", "rendr@github.com").unwrap();
+    let parent = repo.head().ok().map(|h| h.peel_to_commit().unwrap());
+    let parents: Vec<_> = parent.iter().collect();
+
+    repo.commit(Some("HEAD"), &sig, &sig, contents, &tree, &parents)
+        .unwrap()
+}
+
+#[test]
+fn remote_source_checks_out_the_requested_ref() {
+    let dir = TempDir::new("blueprint-repo").unwrap();
+    let repo = Repository::init(dir.path()).unwrap();
+
+    let v1 = commit_file(&repo, "metadata.yaml", "version: 1");
+    repo.tag_lightweight("v1", &repo.find_object(v1, None).unwrap(), false)
+        .unwrap();
+    let v2 = commit_file(&repo, "metadata.yaml", "version: 2");
+
+    let url = format!("file://{}", dir.path().display());
+    let cache_dir = TempDir::new("rendr-cache").unwrap();
+    let cache = Cache::new(cache_dir.path(), false);
+
+    let source = Source::with_cache(&url, None, &cache).unwrap();
+    assert_eq!(source.commit(), Some(v2.to_string()));
+    assert_eq!(source.latest_tag().unwrap(), Some(String::from("v1")));
+    assert_eq!(source.to_string("."), url);
+
+    let source = Source::with_cache(&format!("{}#v1", url), None, &cache).unwrap();
+    assert_eq!(source.git_ref(), Some("v1"));
+    assert_eq!(source.commit(), Some(v1.to_string()));
+    assert_eq!(
+        std::fs::read_to_string(source.path().join("metadata.yaml")).unwrap(),
+        "version: 1"
+    );
+
+    let source = Source::with_cache(&format!("{}#{}", url, v1), None, &cache).unwrap();
+    assert_eq!(source.commit(), Some(v1.to_string()));
+
+    // New commits are fetched into the cache, unless it's offline.
+    let v3 = commit_file(&repo, "metadata.yaml", "version: 3");
+    let offline = Cache::new(cache_dir.path(), true);
+    let source = Source::with_cache(&url, None, &offline).unwrap();
+    assert_eq!(source.commit(), Some(v2.to_string()));
+    let source = Source::with_cache(&url, None, &cache).unwrap();
+    assert_eq!(source.commit(), Some(v3.to_string()));
+    assert_eq!(cache.entries().unwrap()[0].url, url);
+
+    let other_cache = TempDir::new("rendr-cache").unwrap();
+    match Source::with_cache(&url, None, &Cache::new(other_cache.path(), true)) {
+        Err(BlueprintSourceError::NotCached(u)) => assert_eq!(u, url),
+        _ => panic!("expected a not cached error"),
+    }
+
+    match Source::with_cache(&format!("{}#nope", url), None, &cache) {
+        Err(BlueprintSourceError::RefNotFound(r)) => assert_eq!(r, "nope"),
+        _ => panic!("expected a missing ref error"),
+    }
+}
+
+#[test]
+fn subdirectories_are_split_off_git_urls() {
+    assert_eq!(
+        split_subdir("https://github.com/org/blueprints//service/api"),
+        ("https://github.com/org/blueprints", Some("service/api"))
+    );
+    assert_eq!(
+        split_subdir("git@github.com:org/blueprints.git//service"),
+        ("git@github.com:org/blueprints.git", Some("service"))
+    );
+    assert_eq!(
+        split_subdir("file:///tmp/blueprints"),
+        ("file:///tmp/blueprints", None)
+    );
+    assert_eq!(
+        locator(
+            "https://github.com/org/blueprints",
+            Some("service"),
+            Some("v1")
+        ),
+        "https://github.com/org/blueprints//service#v1"
+    );
+}
+
+#[test]
+fn aliases_are_resolved_to_their_sources() {
+    let mut config = UserConfig::default();
+    config.aliases.insert(
+        String::from("rust-service"),
+        String::from("git@github.com:org/blueprints.git//rust-service"),
+    );
+    config.aliases.insert(
+        String::from("blueprints"),
+        String::from("https://github.com/org/blueprints#stable"),
+    );
+
+    assert_eq!(
+        resolve_alias("rust-service", &config),
+        "git@github.com:org/blueprints.git//rust-service"
+    );
+    assert_eq!(
+        resolve_alias("rust-service#v2", &config),
+        "git@github.com:org/blueprints.git//rust-service#v2"
+    );
+    assert_eq!(
+        resolve_alias("rust-service//grpc", &config),
+        "git@github.com:org/blueprints.git//rust-service/grpc"
+    );
+    assert_eq!(
+        resolve_alias("blueprints//go-service", &config),
+        "https://github.com/org/blueprints//go-service#stable"
+    );
+    assert_eq!(
+        resolve_alias("blueprints#main", &config),
+        "https://github.com/org/blueprints#main"
+    );
+    assert_eq!(
+        resolve_alias("https://github.com/org/other", &config),
+        "https://github.com/org/other"
+    );
+}
+
+#[test]
+fn rewritten_urls_are_fetched_from_the_mirror() {
+    let dir = TempDir::new("blueprint-mirror").unwrap();
+    let repo = Repository::init(dir.path()).unwrap();
+    let commit = commit_file(&repo, "metadata.yaml", "version: 1");
+
+    let mut config = UserConfig::default();
+    config.rewrites.push(crate::config::UrlRewrite {
+        url: format!("file://{}/", dir.path().parent().unwrap().display()),
+        instead_of: String::from("https://github.com/org/"),
+    });
+    let url = format!(
+        "https://github.com/org/{}",
+        dir.path().file_name().unwrap().to_str().unwrap()
+    );
+    let cache_dir = TempDir::new("rendr-cache").unwrap();
+    let cache = Cache::new(cache_dir.path(), false);
+
+    let source = Source::with_config(&url, None, &cache, &config).unwrap();
+    assert_eq!(source.commit(), Some(commit.to_string()));
+    assert_eq!(source.to_string("."), url);
+}
+
+#[test]
+fn remote_source_points_at_the_blueprint_subdirectory() {
+    let dir = TempDir::new("blueprint-repo").unwrap();
+    let repo = Repository::init(dir.path()).unwrap();
+    std::fs::create_dir(dir.path().join("service")).unwrap();
+    commit_file(&repo, "service/metadata.yaml", "version: 1");
+
+    let url = format!("file://{}", dir.path().display());
+    let cache_dir = TempDir::new("rendr-cache").unwrap();
+    let cache = Cache::new(cache_dir.path(), false);
+
+    let source = Source::with_cache(&format!("{}//service", url), None, &cache).unwrap();
+    assert_eq!(source.subdir(), Some("service"));
+    assert!(source.path().join("metadata.yaml").exists());
+    assert_eq!(source.to_string("."), url);
+
+    match Source::with_cache(&format!("{}//nope", url), None, &cache) {
+        Err(BlueprintSourceError::PathNotFound(p)) => assert_eq!(p, "nope"),
+        _ => panic!("expected a missing path error"),
+    }
+}
+
+#[test]
+fn archive_source_extracts_the_blueprint() {
+    let dir = TempDir::new("blueprint-archive").unwrap();
+    let archive = dir.path().join("blueprint.zip");
+    archive::create_zip(Path::new("test_assets/example_blueprint"), &archive);
+
+    let source = Source::new(archive.to_str().unwrap(), None).unwrap();
+
+    assert!(source.path().join("metadata.yaml").exists());
+    assert_eq!(
+        source.to_string(dir.path()),
+        "blueprint.zip",
+        "the archive itself is recorded as the source"
+    );
+}
+
+/// Serves the body to every request on a local port, and sends the requests
+/// it received back through the returned channel.
+#[cfg(test)]
+fn serve(body: Vec<u8>) -> (String, std::sync::mpsc::Receiver<String>) {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (requests, received) = std::sync::mpsc::channel();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = String::new();
+            let mut reader = BufReader::new(&mut stream);
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                request.push_str(&line);
+            }
+            requests.send(request).unwrap();
+
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            )
+            .unwrap();
+            stream.write_all(&body).unwrap();
+        }
+    });
+
+    (url, received)
+}
+
+#[test]
+fn http_source_downloads_and_verifies_the_archive() {
+    let dir = TempDir::new("blueprint-archive").unwrap();
+    let archive = dir.path().join("blueprint.tar.gz");
+    archive::create_tar_gz(
+        Path::new("test_assets/example_blueprint"),
+        "example_blueprint",
+        &archive,
+    );
+    let sha256 = download::sha256(&archive).unwrap();
+
+    let (server, requests) = serve(std::fs::read(&archive).unwrap());
+    let url = format!("{}/blueprints/example-1.0.tar.gz", server);
+    let fragment = format!("sha256={}", sha256);
+    let cache_dir = TempDir::new("rendr-cache").unwrap();
+    let cache = Cache::new(cache_dir.path(), false);
+
+    let source = Source::http(
+        &url,
+        &url,
+        ArchiveKind::TarGz,
+        Some(&fragment),
+        &cache,
+        Some("hunter2"),
+    )
+    .unwrap();
+
+    assert!(source.path().join("metadata.yaml").exists());
+    assert_eq!(source.to_string("."), url);
+    assert_eq!(source.sha256(), Some(sha256.as_str()));
+    let request = requests.recv().unwrap();
+    assert!(request.starts_with("GET /blueprints/example-1.0.tar.gz"));
+    assert!(request.contains("Authorization: Bearer hunter2"));
+
+    // The archive is cached by its checksum, so it can be used offline.
+    let offline = Cache::new(cache_dir.path(), true);
+    let source = Source::http(
+        &url,
+        &url,
+        ArchiveKind::TarGz,
+        Some(&fragment),
+        &offline,
+        None,
+    )
+    .unwrap();
+    assert!(source.path().join("metadata.yaml").exists());
+    assert!(requests.try_recv().is_err());
+    assert_eq!(offline.entries().unwrap()[0].url, url);
+
+    match Source::http(
+        &url,
+        &url,
+        ArchiveKind::TarGz,
+        Some("sha256=0123"),
+        &cache,
+        None,
+    ) {
+        Err(BlueprintSourceError::ChecksumMismatch { expected, actual }) => {
+            assert_eq!(expected, "0123");
+            assert_eq!(actual, sha256);
+        }
+        _ => panic!("expected a checksum mismatch"),
+    }
+}
@@ -1,44 +1,400 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::iter::FromIterator;
 
+use indexmap::IndexMap;
+use serde::de::{self, Deserializer, MapAccess, SeqAccess, Visitor};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+use super::encryption::{EncryptionError, LocalKey};
+
+/// A single value. Besides plain strings, values can be arbitrary trees of
+/// lists and maps, like `database.host` or a list of `services`.
+///
+/// Other YAML scalars, like numbers and booleans, are read as strings.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Value {
+    String(String),
+    List(Vec<Value>),
+    Map(IndexMap<String, Value>),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn get(&self, path: &[&str]) -> Option<&Value> {
+        let (first, rest) = match path.split_first() {
+            Some(split) => split,
+            None => return Some(self),
+        };
+
+        match self {
+            Value::Map(map) => map.get(*first)?.get(rest),
+            Value::List(list) => list.get(first.parse::<usize>().ok()?)?.get(rest),
+            Value::String(_) => None,
+        }
+    }
+
+    fn insert(&mut self, path: &[&str], value: Value) {
+        let (first, rest) = match path.split_first() {
+            Some(split) => split,
+            None => {
+                *self = value;
+                return;
+            }
+        };
+
+        match self {
+            Value::Map(map) => {
+                return map
+                    .entry(first.to_string())
+                    .or_insert_with(|| Value::Map(IndexMap::new()))
+                    .insert(rest, value)
+            }
+            Value::List(list) => {
+                if let Some(item) = first.parse::<usize>().ok().and_then(|i| list.get_mut(i)) {
+                    return item.insert(rest, value);
+                }
+            }
+            Value::String(_) => {}
+        }
+
+        // Anything that isn't a map (or an existing list item) gets replaced.
+        *self = Value::Map(IndexMap::new());
+        self.insert(path, value);
+    }
+
+    fn remove(&mut self, path: &[&str]) -> Option<Value> {
+        match (self, path) {
+            (Value::Map(map), [last]) => map.shift_remove(*last),
+            (Value::Map(map), [first, rest @ ..]) => map.get_mut(*first)?.remove(rest),
+            (Value::List(list), [first, rest @ ..]) if !rest.is_empty() => {
+                list.get_mut(first.parse::<usize>().ok()?)?.remove(rest)
+            }
+            _ => None,
+        }
+    }
+
+    /// Merges the other value into this one. Maps are merged key by key,
+    /// anything else is replaced.
+    fn merge(&mut self, other: &Value) {
+        match (self, other) {
+            (Value::Map(map), Value::Map(other)) => {
+                for (k, v) in other {
+                    match map.get_mut(k) {
+                        Some(existing) => existing.merge(v),
+                        None => {
+                            map.insert(k.clone(), v.clone());
+                        }
+                    }
+                }
+            }
+            (this, other) => *this = other.clone(),
+        }
+    }
+
+    /// Reorders nested maps by the declaration position of their dotted
+    /// paths. Keys that weren't declared keep their order after the others.
+    fn sort_by_declaration(&mut self, prefix: &str, position: &impl Fn(&str) -> usize) {
+        if let Value::Map(map) = self {
+            map.sort_by(|k1, _, k2, _| {
+                position(&format!("{}.{}", prefix, k1))
+                    .cmp(&position(&format!("{}.{}", prefix, k2)))
+            });
+            for (k, v) in map.iter_mut() {
+                v.sort_by_declaration(&format!("{}.{}", prefix, k), position);
+            }
+        }
+    }
+
+    /// All the strings in this value, along with their dotted paths, like
+    /// `database.host` or `services.0`.
+    pub fn flatten(&self, prefix: &str) -> Vec<(String, &str)> {
+        let mut result = Vec::new();
+        self.flatten_into(prefix.to_string(), &mut result);
+
+        result
+    }
+
+    fn flatten_into<'v>(&'v self, prefix: String, result: &mut Vec<(String, &'v str)>) {
+        match self {
+            Value::String(s) => result.push((prefix, s)),
+            Value::List(list) => {
+                for (i, v) in list.iter().enumerate() {
+                    v.flatten_into(format!("{}.{}", prefix, i), result);
+                }
+            }
+            Value::Map(map) => {
+                for (k, v) in map {
+                    v.flatten_into(format!("{}.{}", prefix, k), result);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::String(s) => write!(f, "{}", s),
+            _ => write!(
+                f,
+                "{}",
+                serde_json::to_string(self).map_err(|_| fmt::Error)?
+            ),
+        }
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl From<&String> for Value {
+    fn from(s: &String) -> Self {
+        Value::String(s.clone())
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<&Value> for Value {
+    fn from(v: &Value) -> Self {
+        v.clone()
+    }
+}
+
+// Enable deserialization from any YAML (or JSON) tree.
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a string, a list or a map of values")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Value, E> {
+        Ok(Value::String(value.to_string()))
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<Value, E> {
+        Ok(Value::String(value.to_string()))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Value, E> {
+        Ok(Value::String(value.to_string()))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Value, E> {
+        Ok(Value::String(value.to_string()))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Value, E> {
+        Ok(Value::String(value.to_string()))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::String(String::new()))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut list = Vec::new();
+        while let Some(v) = seq.next_element()? {
+            list.push(v);
+        }
+
+        Ok(Value::List(list))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Value, A::Error> {
+        let mut map = IndexMap::new();
+        while let Some((k, v)) = access.next_entry::<Value, Value>()? {
+            match k {
+                Value::String(k) => map.insert(k, v),
+                _ => return Err(de::Error::custom("map keys must be strings")),
+            };
+        }
+
+        Ok(Value::Map(map))
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 #[serde(transparent)]
 pub struct Values {
     #[serde(flatten)]
-    inner: HashMap<String, String>,
+    inner: IndexMap<String, Value>,
 }
 
 impl Values {
     pub fn new() -> Self {
         Values {
-            inner: HashMap::new(),
+            inner: IndexMap::new(),
         }
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
-        self.inner.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.inner.iter().map(|(k, v)| (k.as_str(), v))
     }
 
-    pub fn get(&self, k: &str) -> Option<&String> {
-        self.inner.get(k)
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// The string at the given dotted path, e.g. `database.host`.
+    pub fn get(&self, path: &str) -> Option<&String> {
+        match self.get_value(path)? {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The value at the given dotted path, e.g. `database` or `services.0`.
+    pub fn get_value(&self, path: &str) -> Option<&Value> {
+        let path: Vec<_> = path.split('.').collect();
+
+        self.inner.get(path[0])?.get(&path[1..])
     }
 
-    pub fn map(&self) -> &HashMap<String, String> {
-        &self.inner
+    pub fn contains(&self, path: &str) -> bool {
+        self.get_value(path).is_some()
     }
 
-    pub fn map_str(&self) -> HashMap<&str, &str> {
+    /// Sets the value at the given dotted path, creating any missing maps
+    /// along the way.
+    pub fn insert(&mut self, path: &str, value: impl Into<Value>) {
+        let path: Vec<_> = path.split('.').collect();
+
         self.inner
-            .iter()
-            .map(|(name, value)| (name.as_str(), value.as_str()))
-            .collect()
+            .entry(path[0].to_string())
+            .or_insert_with(|| Value::Map(IndexMap::new()))
+            .insert(&path[1..], value.into());
+    }
+
+    pub fn remove(&mut self, path: &str) -> Option<Value> {
+        let path: Vec<_> = path.split('.').collect();
+
+        match path.split_first() {
+            Some((first, [])) => self.inner.shift_remove(*first),
+            Some((first, rest)) => self.inner.get_mut(*first)?.remove(rest),
+            None => None,
+        }
+    }
+
+    /// Merges the other values into these ones, with the other values taking
+    /// precedence. Nested maps are merged key by key.
+    pub fn merge(&mut self, other: &Values) {
+        for (k, v) in other.iter() {
+            match self.inner.get_mut(k) {
+                Some(existing) => existing.merge(v),
+                None => {
+                    self.inner.insert(k.to_string(), v.clone());
+                }
+            }
+        }
+    }
+
+    /// A copy of these values with the given dotted paths left out.
+    pub fn without(&self, paths: &[&str]) -> Values {
+        let mut values = self.clone();
+        for path in paths {
+            values.remove(path);
+        }
+
+        values
+    }
+
+    /// Reorders the values to follow the given declaration order, like the
+    /// order of a blueprint's `values`, with any other values sorted by name
+    /// after them. Nested maps follow the declaration order of their dotted
+    /// names, and otherwise keep their own order.
+    pub fn sort_by_declaration<'n>(&mut self, names: impl IntoIterator<Item = &'n str>) {
+        let names: Vec<_> = names.into_iter().collect();
+        let position = |path: &str| {
+            names
+                .iter()
+                .position(|name| {
+                    name.strip_prefix(path)
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+                })
+                .unwrap_or(usize::MAX)
+        };
+
+        self.inner
+            .sort_by(|k1, _, k2, _| (position(k1), k1).cmp(&(position(k2), k2)));
+        for (k, v) in self.inner.iter_mut() {
+            v.sort_by_declaration(k, &position);
+        }
+    }
+
+    /// All the strings in these values, along with their dotted paths.
+    pub fn flattened(&self) -> Vec<(String, &str)> {
+        self.iter().flat_map(|(k, v)| v.flatten(k)).collect()
+    }
+
+    /// The values as environment variables for scripts, named by
+    /// [`env_var_name`], like `DATABASE_HOST` for `database.host`. Top-level
+    /// strings are also available by their own name, as they were before
+    /// values could be nested.
+    pub fn env_vars(&self) -> Vec<(String, String)> {
+        let mut vars = Vec::new();
+        for (k, v) in self.iter() {
+            if let Value::String(s) = v {
+                if env_var_name(k) != k {
+                    vars.push((k.to_string(), s.clone()));
+                }
+            }
+            for (path, s) in v.flatten(k) {
+                vars.push((env_var_name(&path), s.to_string()));
+            }
+        }
+
+        vars
+    }
+}
+
+/// The environment variable for the value at a dotted path: the path in upper
+/// case, with `_` for dots, e.g. `DATABASE_HOST` for `database.host`.
+pub fn env_var_name(path: &str) -> String {
+    path.replace('.', "_").to_uppercase()
+}
+
+impl<K: AsRef<str>, V: Into<Value>> FromIterator<(K, V)> for Values {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut values = Values::new();
+        for (k, v) in iter {
+            values.insert(k.as_ref(), v);
+        }
+
+        values
     }
 }
 
 impl From<HashMap<String, String>> for Values {
     fn from(h: HashMap<String, String>) -> Self {
-        Self { inner: h }
+        h.into_iter().collect()
     }
 }
 
@@ -49,40 +405,211 @@ impl From<HashMap<String, String>> for Values {
 // https://github.com/purpleprotocol/hashcow
 impl From<HashMap<&str, &str>> for Values {
     fn from(h: HashMap<&str, &str>) -> Self {
-        Self {
-            inner: h
-                .into_iter()
-                .map(|(k, v)| (k.to_string(), v.to_string()))
-                .collect(),
-        }
+        h.into_iter().collect()
     }
 }
 
 impl From<&HashMap<&str, &str>> for Values {
     fn from(h: &HashMap<&str, &str>) -> Self {
-        Self {
-            inner: h
-                .into_iter()
-                .map(|(k, v)| (k.to_string(), v.to_string()))
-                .collect(),
-        }
+        h.iter().map(|(k, v)| (*k, *v)).collect()
     }
 }
 
 impl From<clap::Values<'_>> for Values {
     fn from(h: clap::Values) -> Self {
+        h.into_iter().map(|s| parse_value(s).unwrap()).collect()
+    }
+}
+
+/// A value as it is stored in the `.rendr.yaml` file, either in plain text or
+/// encrypted with a [`LocalKey`].
+// The encrypted variant comes first, since a plain value may be a map too.
+// Only a map with nothing but an `encrypted` key is read as an encrypted value.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum StoredValue {
+    Encrypted { encrypted: String },
+    Plain(Value),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(transparent)]
+pub struct StoredValues {
+    inner: IndexMap<String, StoredValue>,
+}
+
+impl StoredValues {
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &StoredValue)> {
+        self.inner.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    pub fn get(&self, k: &str) -> Option<&StoredValue> {
+        self.inner.get(k)
+    }
+
+    /// Whether there's a value at the given dotted path, plain or encrypted.
+    pub fn contains(&self, path: &str) -> bool {
+        self.inner.contains_key(path) || self.plain_value(path).is_some()
+    }
+
+    pub fn is_encrypted(&self, k: &str) -> bool {
+        matches!(self.inner.get(k), Some(StoredValue::Encrypted { .. }))
+    }
+
+    /// The names of the encrypted values. Nested values are encrypted on
+    /// their own, under their dotted path, like `database.password`.
+    pub fn encrypted_names(&self) -> Vec<&str> {
+        self.iter()
+            .filter(|(k, _)| self.is_encrypted(k))
+            .map(|(k, _)| k)
+            .collect()
+    }
+
+    /// The plain value at the given dotted path.
+    fn plain_value(&self, path: &str) -> Option<&Value> {
+        let path: Vec<_> = path.split('.').collect();
+
+        match self.inner.get(path[0])? {
+            StoredValue::Plain(value) => value.get(&path[1..]),
+            StoredValue::Encrypted { .. } => None,
+        }
+    }
+
+    /// Removes the plain value at the given dotted path, and returns it.
+    fn take_plain_value(&mut self, path: &str) -> Option<Value> {
+        let parts: Vec<_> = path.split('.').collect();
+
+        match (self.inner.get_mut(parts[0])?, &parts[1..]) {
+            (StoredValue::Plain(_), []) => match self.inner.shift_remove(path)? {
+                StoredValue::Plain(value) => Some(value),
+                StoredValue::Encrypted { .. } => None,
+            },
+            (StoredValue::Plain(value), rest) => value.remove(rest),
+            (StoredValue::Encrypted { .. }, _) => None,
+        }
+    }
+
+    /// Stores a plain value at the given dotted path, creating any missing
+    /// maps along the way.
+    fn insert_plain_value(&mut self, path: &str, value: Value) {
+        let parts: Vec<_> = path.split('.').collect();
+
+        match self.inner.get_mut(parts[0]) {
+            Some(StoredValue::Plain(existing)) => existing.insert(&parts[1..], value),
+            _ => {
+                let mut new = Value::Map(IndexMap::new());
+                new.insert(&parts[1..], value);
+                self.inner
+                    .insert(parts[0].to_string(), StoredValue::Plain(new));
+            }
+        }
+    }
+
+    /// Stores the value at the given dotted path encrypted. If no plaintext
+    /// is provided, the existing plain value is encrypted.
+    pub fn encrypt(
+        &mut self,
+        path: &str,
+        plaintext: Option<&Value>,
+        key: &LocalKey,
+    ) -> Result<(), EncryptionError> {
+        let existing = self.take_plain_value(path);
+        let plaintext = match (plaintext, existing) {
+            (Some(plaintext), _) => plaintext.clone(),
+            (None, Some(existing)) => existing,
+            (None, None) => return Ok(()),
+        };
+
+        // Values are encrypted as JSON, so that lists and maps survive the trip.
+        let plaintext =
+            serde_json::to_string(&plaintext).map_err(|_| EncryptionError::InvalidPlaintext)?;
+        let encrypted = key.encrypt(&plaintext)?;
+        self.inner
+            .insert(path.to_string(), StoredValue::Encrypted { encrypted });
+
+        Ok(())
+    }
+
+    pub fn decrypt(&mut self, path: &str, key: &LocalKey) -> Result<(), EncryptionError> {
+        if let Some(StoredValue::Encrypted { encrypted }) = self.inner.get(path) {
+            let plaintext = decrypt_value(encrypted, key)?;
+            self.inner.shift_remove(path);
+            self.insert_plain_value(path, plaintext);
+        }
+
+        Ok(())
+    }
+
+    /// Encrypts the values that are encrypted in `previous`. The existing
+    /// ciphertext is kept for values that haven't changed.
+    pub fn encrypt_like(
+        &mut self,
+        previous: &StoredValues,
+        key: &LocalKey,
+    ) -> Result<(), EncryptionError> {
+        for name in previous.encrypted_names() {
+            let plaintext = match self.take_plain_value(name) {
+                Some(plaintext) => plaintext,
+                None => continue,
+            };
+
+            match previous.get(name) {
+                Some(StoredValue::Encrypted { encrypted })
+                    if decrypt_value(encrypted, key)? == plaintext =>
+                {
+                    self.inner.insert(
+                        name.to_string(),
+                        StoredValue::Encrypted {
+                            encrypted: encrypted.clone(),
+                        },
+                    );
+                }
+                _ => self.encrypt(name, Some(&plaintext), key)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Decrypts all the values. A key is only needed if some of them are
+    /// encrypted.
+    pub fn decrypted(&self, key: Option<&LocalKey>) -> Result<Values, EncryptionError> {
+        let mut values = Values::new();
+
+        for (k, v) in self.iter() {
+            let plaintext = match (v, key) {
+                (StoredValue::Plain(plaintext), _) => plaintext.clone(),
+                (StoredValue::Encrypted { encrypted }, Some(key)) => decrypt_value(encrypted, key)?,
+                (StoredValue::Encrypted { .. }, None) => {
+                    return Err(EncryptionError::KeyNotFound(LocalKey::default_path()?))
+                }
+            };
+
+            // Nested values encrypted on their own are merged into their maps.
+            let mut value = Values::new();
+            value.insert(k, plaintext);
+            values.merge(&value);
+        }
+
+        Ok(values)
+    }
+}
+
+impl From<&Values> for StoredValues {
+    fn from(values: &Values) -> Self {
         Self {
-            inner: h
-                .into_iter()
-                .map(|s| {
-                    let (k, v) = parse_value(s).unwrap();
-                    (k.to_string(), v.to_string())
-                })
+            inner: values
+                .iter()
+                .map(|(k, v)| (k.to_string(), StoredValue::Plain(v.clone())))
                 .collect(),
         }
     }
 }
 
+fn decrypt_value(encrypted: &str, key: &LocalKey) -> Result<Value, EncryptionError> {
+    serde_json::from_str(&key.decrypt(encrypted)?).map_err(|_| EncryptionError::InvalidPlaintext)
+}
+
 fn parse_value(s: &str) -> Result<(&str, &str), String> {
     let pos = s.find(":").ok_or(format!("Invalid value `{}`", s))?;
 
@@ -91,3 +618,123 @@ fn parse_value(s: &str) -> Result<(&str, &str), String> {
 
     Ok((result.0, result.1))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_values_are_read_from_yaml() {
+        let yaml = "
+name: my-project
+port: 8080
+database:
+  host: localhost
+services:
+- api
+- worker
+";
+        let values: Values = serde_yaml::from_str(yaml).unwrap();
+
+        assert_eq!(values.get("name").unwrap(), "my-project");
+        assert_eq!(values.get("port").unwrap(), "8080");
+        assert_eq!(values.get("database.host").unwrap(), "localhost");
+        assert_eq!(values.get("services.1").unwrap(), "worker");
+        assert!(values.get("database").is_none());
+        assert!(values.contains("database"));
+    }
+
+    #[test]
+    fn nested_maps_keep_their_order() {
+        let mut values: Values =
+            serde_yaml::from_str("database:\n  port: 5432\n  host: localhost\n").unwrap();
+        assert_eq!(
+            values.flattened(),
+            vec![
+                ("database.port".to_string(), "5432"),
+                ("database.host".to_string(), "localhost"),
+            ]
+        );
+
+        values.insert("database.user", "admin");
+        values.sort_by_declaration(vec!["database.user"]);
+        assert_eq!(values.flattened()[0].0, "database.user");
+        assert_eq!(values.flattened()[1].0, "database.port");
+    }
+
+    #[test]
+    fn dotted_paths_create_nested_values() {
+        let mut values = Values::new();
+        values.insert("database.host", "localhost");
+        values.insert("database.port", "5432");
+
+        let mut overrides = Values::new();
+        overrides.insert("database.host", "db.example.com");
+        values.merge(&overrides);
+
+        assert_eq!(values.get("database.host").unwrap(), "db.example.com");
+        assert_eq!(values.get("database.port").unwrap(), "5432");
+        assert_eq!(
+            values.without(&["database.port"]).flattened(),
+            vec![("database.host".to_string(), "db.example.com")]
+        );
+    }
+
+    #[test]
+    fn values_are_sorted_by_declaration() {
+        let mut values = Values::new();
+        values.insert("zzz", "1");
+        values.insert("aaa", "2");
+        values.insert("database.port", "5432");
+        values.insert("name", "my-project");
+        values.insert("database.host", "localhost");
+
+        values.sort_by_declaration(vec!["name", "database.host", "database.port"]);
+
+        assert_eq!(
+            values.flattened(),
+            vec![
+                ("name".to_string(), "my-project"),
+                ("database.host".to_string(), "localhost"),
+                ("database.port".to_string(), "5432"),
+                ("aaa".to_string(), "2"),
+                ("zzz".to_string(), "1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_values_are_flattened_for_the_environment() {
+        let mut values = Values::new();
+        values.insert("name", "my-project");
+        values.insert("database.host", "localhost");
+
+        let mut env = values.env_vars();
+        env.sort();
+
+        assert_eq!(
+            env,
+            vec![
+                ("DATABASE_HOST".to_string(), "localhost".to_string()),
+                ("NAME".to_string(), "my-project".to_string()),
+                ("name".to_string(), "my-project".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn maps_with_an_encrypted_key_and_more_are_plain() {
+        let yaml = "
+secret:
+  encrypted: abc
+settings:
+  encrypted: \"no\"
+  name: x
+";
+        let values: StoredValues = serde_yaml::from_str(yaml).unwrap();
+        let values: Vec<_> = values.iter().collect();
+
+        assert!(matches!(values[0].1, StoredValue::Encrypted { .. }));
+        assert!(matches!(values[1].1, StoredValue::Plain(Value::Map(_))));
+    }
+}
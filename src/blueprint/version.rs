@@ -0,0 +1,343 @@
+//! Blueprint versions and version ranges. Versions are semantic versions like
+//! `2.3.0`, but the plain integers older blueprints use are still read, with
+//! `2` meaning `2.0.0`.
+
+use std::convert::TryFrom;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BlueprintVersion(semver::Version);
+
+impl BlueprintVersion {
+    pub fn new(major: u64, minor: u64, patch: u64) -> Self {
+        BlueprintVersion(semver::Version::new(major, minor, patch))
+    }
+
+    /// The range of versions that are compatible with this one, according to
+    /// semver: `^2` for `2.3.0`, or `^0.3` for `0.3.1`.
+    pub fn compatible_range(&self) -> VersionRange {
+        let range = match (self.0.major, self.0.minor) {
+            (0, 0) => format!("^0.0.{}", self.0.patch),
+            (0, minor) => format!("^0.{}", minor),
+            (major, _) => format!("^{}", major),
+        };
+
+        range.parse().expect("compatible ranges are always valid")
+    }
+
+    /// The range recorded for a project on this version. The project's
+    /// previous range is kept as long as it includes this version, so it's
+    /// only widened by upgrades past it.
+    pub fn range_from(&self, previous: Option<&VersionRange>) -> VersionRange {
+        match previous {
+            Some(range) if range.matches(self) => range.clone(),
+            _ => self.compatible_range(),
+        }
+    }
+}
+
+impl FromStr for BlueprintVersion {
+    type Err = VersionError;
+
+    /// Parses a version, filling in missing minor and patch numbers, so `2`
+    /// and `2.3` are read as `2.0.0` and `2.3.0`. A leading `v` is ignored.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim();
+        let trimmed = trimmed.strip_prefix('v').unwrap_or(trimmed);
+
+        // Pre-release and build metadata may contain dots, too.
+        let core_end = trimmed.find(['-', '+']).unwrap_or(trimmed.len());
+        let (core, rest) = trimmed.split_at(core_end);
+        let padding = match core.matches('.').count() {
+            0 => ".0.0",
+            1 => ".0",
+            _ => "",
+        };
+
+        semver::Version::parse(&format!("{}{}{}", core, padding, rest))
+            .map(BlueprintVersion)
+            .map_err(|e| VersionError::InvalidVersion(s.to_string(), e.to_string()))
+    }
+}
+
+impl From<u64> for BlueprintVersion {
+    fn from(major: u64) -> Self {
+        BlueprintVersion::new(major, 0, 0)
+    }
+}
+
+impl Display for BlueprintVersion {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Serialize for BlueprintVersion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for BlueprintVersion {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(VersionVisitor)
+    }
+}
+
+struct VersionVisitor;
+
+impl<'de> Visitor<'de> for VersionVisitor {
+    type Value = BlueprintVersion;
+
+    fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "a version like 2.3.0, or a whole number")
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        Ok(BlueprintVersion::from(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        u64::try_from(v)
+            .map(BlueprintVersion::from)
+            .map_err(|_| E::custom(format!("invalid version {}", v)))
+    }
+
+    // YAML reads an unquoted `2.3` as a number, which would turn `2.10` into
+    // `2.1`, so that's never guessed at.
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        Err(E::custom(format!(
+            "ambiguous version {}, write it as a full version like {}.0 or quote it",
+            v, v
+        )))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        v.parse().map_err(E::custom)
+    }
+}
+
+/// A range of versions, like `^2` or `>=1.2, <3`. A plain version, or a
+/// legacy integer, means the versions compatible with it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VersionRange(semver::VersionReq);
+
+impl VersionRange {
+    pub fn matches(&self, version: &BlueprintVersion) -> bool {
+        self.0.matches(&version.0)
+    }
+}
+
+impl FromStr for VersionRange {
+    type Err = VersionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        semver::VersionReq::parse(s.trim())
+            .map(VersionRange)
+            .map_err(|e| VersionError::InvalidRange(s.to_string(), e.to_string()))
+    }
+}
+
+impl Display for VersionRange {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Serialize for VersionRange {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for VersionRange {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(RangeVisitor)
+    }
+}
+
+struct RangeVisitor;
+
+impl<'de> Visitor<'de> for RangeVisitor {
+    type Value = VersionRange;
+
+    fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "a version range like ^2 or >=1.2, <3")
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        self.visit_str(&v.to_string())
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        self.visit_str(&v.to_string())
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        self.visit_str(&v.to_string())
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        v.parse().map_err(E::custom)
+    }
+}
+
+/// How git tags are named after blueprint versions, like `v{version}` for
+/// tags such as `v2.3.0`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagPattern {
+    prefix: String,
+    suffix: String,
+}
+
+impl TagPattern {
+    const PLACEHOLDER: &'static str = "{version}";
+
+    /// The version a tag stands for, if it matches the pattern.
+    pub fn version_of(&self, tag: &str) -> Option<BlueprintVersion> {
+        let version = tag.strip_prefix(&self.prefix)?.strip_suffix(&self.suffix)?;
+
+        version.parse().ok()
+    }
+}
+
+impl Default for TagPattern {
+    fn default() -> Self {
+        TagPattern {
+            prefix: String::from("v"),
+            suffix: String::new(),
+        }
+    }
+}
+
+impl FromStr for TagPattern {
+    type Err = VersionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(Self::PLACEHOLDER) {
+            Some((prefix, suffix)) if !suffix.contains(Self::PLACEHOLDER) => Ok(TagPattern {
+                prefix: prefix.to_string(),
+                suffix: suffix.to_string(),
+            }),
+            _ => Err(VersionError::InvalidTagPattern(s.to_string())),
+        }
+    }
+}
+
+impl Display for TagPattern {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}{}{}", self.prefix, Self::PLACEHOLDER, self.suffix)
+    }
+}
+
+impl Serialize for TagPattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for TagPattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum VersionError {
+    #[error("invalid version `{0}`: {1}")]
+    InvalidVersion(String, String),
+
+    #[error("invalid version range `{0}`: {1}")]
+    InvalidRange(String, String),
+
+    #[error("invalid tag pattern `{0}`, expected exactly one `{{version}}`")]
+    InvalidTagPattern(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(s: &str) -> BlueprintVersion {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn versions_are_read_from_semver_strings_and_integers() {
+        assert_eq!(version("2.3.1"), BlueprintVersion::new(2, 3, 1));
+        assert_eq!(version("2.3"), BlueprintVersion::new(2, 3, 0));
+        assert_eq!(version("v2"), BlueprintVersion::new(2, 0, 0));
+        assert_eq!(version("2-beta.1").to_string(), "2.0.0-beta.1");
+        assert!("two".parse::<BlueprintVersion>().is_err());
+
+        let parsed: Vec<BlueprintVersion> = serde_yaml::from_str("[3, 2.3.0, \"2.10\"]").unwrap();
+        assert_eq!(
+            parsed,
+            vec![version("3"), version("2.3.0"), version("2.10.0")]
+        );
+        assert!(serde_yaml::from_str::<BlueprintVersion>("2.1").is_err());
+
+        assert_eq!(
+            serde_yaml::to_string(&version("2")).unwrap().trim(),
+            "---\n2.0.0"
+        );
+    }
+
+    #[test]
+    fn versions_are_ordered_by_precedence() {
+        assert!(version("2.0.0") < version("2.0.1"));
+        assert!(version("2.0.0-beta") < version("2.0.0"));
+        assert!(version("10") > version("9.9.9"));
+    }
+
+    #[test]
+    fn ranges_match_compatible_versions() {
+        let range = version("2.3.0").compatible_range();
+        assert_eq!(range.to_string(), "^2");
+        assert!(range.matches(&version("2.9.1")));
+        assert!(!range.matches(&version("3.0.0")));
+
+        assert_eq!(version("0.3.1").compatible_range().to_string(), "^0.3");
+
+        let range: VersionRange = serde_yaml::from_str("\">=1.2, <3\"").unwrap();
+        assert!(range.matches(&version("2")));
+        assert!(!range.matches(&version("1.1")));
+        let legacy: VersionRange = serde_yaml::from_str("2").unwrap();
+        assert!(legacy.matches(&version("2.5")));
+    }
+
+    #[test]
+    fn ranges_are_kept_until_a_version_is_outside_them() {
+        let pinned: VersionRange = ">=1, <3".parse().unwrap();
+        assert_eq!(version("2.1.0").range_from(Some(&pinned)), pinned);
+        assert_eq!(version("3.0.0").range_from(Some(&pinned)).to_string(), "^3");
+        assert_eq!(version("1.2.0").range_from(None).to_string(), "^1");
+    }
+
+    #[test]
+    fn tags_are_matched_against_the_tag_pattern() {
+        let pattern = TagPattern::default();
+        assert_eq!(pattern.to_string(), "v{version}");
+        assert_eq!(pattern.version_of("v2.3.0"), Some(version("2.3.0")));
+        assert_eq!(pattern.version_of("v2"), Some(version("2.0.0")));
+        assert_eq!(pattern.version_of("2.3.0"), None);
+        assert_eq!(pattern.version_of("vnext"), None);
+
+        let pattern: TagPattern = "service-{version}-release".parse().unwrap();
+        assert_eq!(
+            pattern.version_of("service-1.2.0-release"),
+            Some(version("1.2.0"))
+        );
+        assert_eq!(pattern.version_of("other-1.2.0-release"), None);
+
+        assert!("v{version}-{version}".parse::<TagPattern>().is_err());
+        assert!("release".parse::<TagPattern>().is_err());
+    }
+}
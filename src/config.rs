@@ -0,0 +1,206 @@
+//! The user's own rendr configuration, read from
+//! `~/.config/rendr/config.yaml`. It holds blueprint aliases and defaults for
+//! command line options, and only ever affects the local machine: projects
+//! record resolved blueprint URLs, so they don't depend on it.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use indexmap::IndexMap;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// The environment variable that overrides the location of the config file.
+pub const CONFIG_ENV_VAR: &str = "RENDR_CONFIG";
+
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct UserConfig {
+    /// Short names for blueprint sources, e.g. `rust-service` for
+    /// `git@github.com:org/blueprints.git//rust-service`.
+    #[serde(default)]
+    pub aliases: IndexMap<String, String>,
+    /// Rules for fetching blueprints from somewhere else than their URL,
+    /// like git's `insteadOf`.
+    #[serde(default)]
+    pub rewrites: Vec<UrlRewrite>,
+    #[serde(default)]
+    pub auth: AuthDefaults,
+    /// The author of blueprints created with `rendr create-blueprint`.
+    pub author: Option<String>,
+    /// Whether rendered projects get a Git repository, unless overridden on
+    /// the command line.
+    #[serde(default, alias = "git-init")]
+    pub git_init: GitInitPolicy,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AuthDefaults {
+    pub user: Option<String>,
+    #[serde(alias = "ssh-key")]
+    pub ssh_key: Option<String>,
+}
+
+/// Fetches URLs starting with `instead_of` from `url` instead, e.g. from an
+/// internal mirror.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct UrlRewrite {
+    pub url: String,
+    #[serde(alias = "insteadOf", alias = "instead-of")]
+    pub instead_of: String,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum GitInitPolicy {
+    /// Always initialize a Git repository.
+    Always,
+    /// Never initialize a Git repository.
+    Never,
+    /// Do what the blueprint's `git_init` setting says.
+    #[default]
+    Blueprint,
+}
+
+impl UserConfig {
+    /// The location of the config file: `RENDR_CONFIG` if it's set, otherwise
+    /// `~/.config/rendr/config.yaml` unless `XDG_CONFIG_HOME` says otherwise.
+    pub fn default_path() -> Option<PathBuf> {
+        if let Some(path) = env::var_os(CONFIG_ENV_VAR).filter(|p| !p.is_empty()) {
+            return Some(PathBuf::from(path));
+        }
+
+        let config_dir = match env::var("XDG_CONFIG_HOME") {
+            Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+
+        Some(config_dir.join("rendr").join("config.yaml"))
+    }
+
+    /// Loads the config from its default location. A missing file is the same
+    /// as an empty one.
+    pub fn load() -> Result<Self, ConfigError> {
+        match Self::default_path() {
+            Some(path) if path.exists() => Self::from_file(path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        debug!("Loading user config from {}", path.display());
+
+        let yaml =
+            fs::read_to_string(path).map_err(|e| ConfigError::ReadError(path.to_path_buf(), e))?;
+        if yaml.trim().is_empty() {
+            return Ok(Self::default());
+        }
+
+        serde_yaml::from_str(&yaml).map_err(|e| ConfigError::ParseError(path.to_path_buf(), e))
+    }
+
+    /// The source an alias stands for.
+    pub fn alias(&self, name: &str) -> Option<&str> {
+        self.aliases.get(name).map(String::as_str)
+    }
+
+    /// The URL to fetch a blueprint from. Like with git, the rule with the
+    /// longest matching prefix wins.
+    pub fn rewrite(&self, url: &str) -> String {
+        let rule = self
+            .rewrites
+            .iter()
+            .filter(|r| url.starts_with(&r.instead_of))
+            .max_by_key(|r| r.instead_of.len());
+
+        match rule {
+            Some(rule) => format!("{}{}", rule.url, &url[rule.instead_of.len()..]),
+            None => url.to_string(),
+        }
+    }
+
+    /// The default SSH key, with a leading `~` expanded to the home directory.
+    pub fn ssh_key(&self) -> Option<String> {
+        let key = self.auth.ssh_key.as_ref()?;
+
+        match (key.strip_prefix("~/"), env::var("HOME")) {
+            (Some(rest), Ok(home)) => Some(Path::new(&home).join(rest).display().to_string()),
+            _ => Some(key.clone()),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("failed to read the config file {0}")]
+    ReadError(PathBuf, #[source] std::io::Error),
+
+    #[error("failed to parse the config file {0}")]
+    ParseError(PathBuf, #[source] serde_yaml::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn user_config_is_read_from_yaml() {
+        let dir = TempDir::new("user-config").unwrap();
+        let path = dir.path().join("config.yaml");
+        fs::write(
+            &path,
+            "aliases:\n  rust-service: git@github.com:org/blueprints.git//rust-service\n\
+             auth:\n  user: jdoe\n  ssh_key: /keys/id_work\n\
+             author: Jane Doe\n\
+             git_init: never\n",
+        )
+        .unwrap();
+
+        let config = UserConfig::from_file(&path).unwrap();
+
+        assert_eq!(
+            config.alias("rust-service"),
+            Some("git@github.com:org/blueprints.git//rust-service")
+        );
+        assert_eq!(config.alias("go-service"), None);
+        assert_eq!(config.auth.user.as_deref(), Some("jdoe"));
+        assert_eq!(config.ssh_key().as_deref(), Some("/keys/id_work"));
+        assert_eq!(config.author.as_deref(), Some("Jane Doe"));
+        assert_eq!(config.git_init, GitInitPolicy::Never);
+
+        fs::write(&path, "").unwrap();
+        assert_eq!(UserConfig::from_file(&path).unwrap(), UserConfig::default());
+
+        fs::write(&path, "alias:\n  typo: true\n").unwrap();
+        assert!(UserConfig::from_file(&path).is_err());
+    }
+
+    #[test]
+    fn urls_are_rewritten_by_the_longest_matching_rule() {
+        let config: UserConfig = serde_yaml::from_str(
+            "rewrites:\n\
+             - url: https://mirror.internal/github/\n  instead_of: https://github.com/\n\
+             - url: /srv/mirrors/blueprints.git\n  instead_of: https://github.com/org/blueprints\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.rewrite("https://github.com/other/repo"),
+            "https://mirror.internal/github/other/repo"
+        );
+        assert_eq!(
+            config.rewrite("https://github.com/org/blueprints"),
+            "/srv/mirrors/blueprints.git"
+        );
+        assert_eq!(
+            config.rewrite("git@github.com:org/repo"),
+            "git@github.com:org/repo"
+        );
+    }
+}
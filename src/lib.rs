@@ -1,5 +1,6 @@
 pub mod blueprint;
-mod pattern;
+pub mod config;
+pub mod pattern;
 pub mod project;
 pub mod templating;
 pub mod utilities;
@@ -1,25 +1,150 @@
 use std::fmt;
-use std::ops::Deref;
+use std::path::Path;
+use std::str::FromStr;
 
 use serde::de::{self, Deserialize, Deserializer, Visitor};
 use serde::{Serialize, Serializer};
+use thiserror::Error;
 
-/// A glob pattern for paths, meant to be directly deserialized into.
+/// A path pattern with the semantics of `.gitignore` files, meant to be
+/// directly deserialized into. Paths are relative to the template root.
 ///
-/// This is effectively a wrapper around patterns in the `glob` crate and follows
-/// [the same rules](glob::Pattern).
-pub struct Pattern(glob::Pattern);
+/// - A pattern without a `/`, like `*.png`, matches names at any depth. One
+///   with a `/`, like `docs/*.md` or `/README.md`, matches paths from the root.
+/// - `*` and `?` don't match `/`, but `**` matches any number of directories,
+///   as in `**/fixtures` or `docs/**`.
+/// - A trailing `/`, like `build/`, only matches directories.
+/// - A leading `!` negates the pattern, so that it re-includes paths matched
+///   by earlier patterns in the same list. `\!` and `\#` match a literal `!`
+///   and `#`.
+///
+/// A pattern that matches a directory matches everything in it, too.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Pattern {
+    source: String,
+    glob: glob::Pattern,
+    negated: bool,
+    anchored: bool,
+    dir_only: bool,
+}
+
+/// Options for matching paths against patterns.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MatchOptions {
+    pub case_sensitive: bool,
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        MatchOptions {
+            case_sensitive: true,
+        }
+    }
+}
+
+impl Pattern {
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    pub fn is_negated(&self) -> bool {
+        self.negated
+    }
+
+    /// Whether the pattern matches the path itself, ignoring negation and the
+    /// directories it's in.
+    pub fn matches(&self, path: &Path, is_dir: bool, options: MatchOptions) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
 
-// Implementing Deref makes our Pattern wrapper almost a drop-in replacement
-// for glob::Pattern.
-impl Deref for Pattern {
-    type Target = glob::Pattern;
+        let options = glob::MatchOptions {
+            case_sensitive: options.case_sensitive,
+            require_literal_separator: true,
+            require_literal_leading_dot: false,
+        };
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+        match self.anchored {
+            true => self.glob.matches_path_with(path, options),
+            false => path
+                .file_name()
+                .is_some_and(|name| self.glob.matches_path_with(Path::new(name), options)),
+        }
+    }
+
+    /// Whether a list of patterns matches a path, like a `.gitignore` file
+    /// would: the last pattern that matches the path decides, and a path in a
+    /// matched directory is always matched.
+    pub fn list_matches(
+        patterns: &[Pattern],
+        path: &Path,
+        is_dir: bool,
+        options: MatchOptions,
+    ) -> bool {
+        let last_match = |path: &Path, is_dir: bool| {
+            patterns
+                .iter()
+                .rev()
+                .find(|pattern| pattern.matches(path, is_dir, options))
+                .is_some_and(|pattern| !pattern.negated)
+        };
+
+        let mut dirs: Vec<_> = path
+            .ancestors()
+            .skip(1)
+            .filter(|p| !p.as_os_str().is_empty())
+            .collect();
+        dirs.reverse();
+
+        dirs.into_iter().any(|dir| last_match(dir, true)) || last_match(path, is_dir)
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = PatternError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negated, rest) = match s.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('\\').unwrap_or(s)),
+        };
+        let (dir_only, rest) = match rest.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        let anchored = rest.contains('/');
+        let rest = rest.strip_prefix('/').unwrap_or(rest);
+
+        if rest.is_empty() {
+            return Err(PatternError::Empty(s.to_string()));
+        }
+        let glob = glob::Pattern::new(rest).map_err(|e| PatternError::Invalid(s.to_string(), e))?;
+
+        Ok(Pattern {
+            source: s.to_string(),
+            glob,
+            negated,
+            anchored,
+            dir_only,
+        })
     }
 }
 
+impl fmt::Debug for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Pattern({:?})", self.source)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum PatternError {
+    #[error("invalid path pattern {0}")]
+    Invalid(String, #[source] glob::PatternError),
+
+    #[error("empty path pattern {0:?}")]
+    Empty(String),
+}
+
 // Enable deserialization from a string.
 struct PatternVisitor;
 
@@ -34,10 +159,12 @@ impl<'de> Visitor<'de> for PatternVisitor {
     where
         E: de::Error,
     {
-        Ok(Pattern(match glob::Pattern::new(value) {
-            Ok(pattern) => pattern,
-            Err(e) => return Err(E::custom(format!("invalid path pattern {}: {}", value, e))),
-        }))
+        value.parse().map_err(|e| match e {
+            PatternError::Invalid(_, e) => {
+                E::custom(format!("invalid path pattern {}: {}", value, e))
+            }
+            e => E::custom(e),
+        })
     }
 }
 
@@ -58,3 +185,88 @@ impl<'de> Deserialize<'de> for Pattern {
         deserializer.deserialize_str(PatternVisitor)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(patterns: &[&str]) -> Vec<Pattern> {
+        patterns.iter().map(|p| p.parse().unwrap()).collect()
+    }
+
+    fn matches(patterns: &[Pattern], path: &str) -> bool {
+        Pattern::list_matches(patterns, Path::new(path), false, MatchOptions::default())
+    }
+
+    #[test]
+    fn patterns_follow_gitignore_rules() {
+        let unanchored = patterns(&["*.png"]);
+        assert!(matches(&unanchored, "logo.png"));
+        assert!(matches(&unanchored, "images/icons/logo.png"));
+
+        let anchored = patterns(&["docs/*.md"]);
+        assert!(matches(&anchored, "docs/index.md"));
+        assert!(!matches(&anchored, "docs/guide/intro.md"));
+        assert!(!matches(&anchored, "src/docs/index.md"));
+        assert!(!matches(&patterns(&["/README.md"]), "docs/README.md"));
+
+        let deep = patterns(&["docs/**/*.md"]);
+        assert!(matches(&deep, "docs/index.md"));
+        assert!(matches(&deep, "docs/guide/intro.md"));
+        assert!(matches(
+            &patterns(&["**/fixtures"]),
+            "src/test/fixtures/data.json"
+        ));
+
+        let dirs = patterns(&["excluded_files"]);
+        assert!(matches(&dirs, "excluded_files/foo"));
+        let dir_only = patterns(&["build/"]);
+        assert!(matches(&dir_only, "build/output.txt"));
+        assert!(!matches(&dir_only, "build"));
+        assert!(Pattern::list_matches(
+            &dir_only,
+            Path::new("build"),
+            true,
+            MatchOptions::default()
+        ));
+    }
+
+    #[test]
+    fn negated_patterns_reinclude_paths() {
+        let list = patterns(&["*.json", "!keep.json"]);
+        assert!(matches(&list, "fixtures/data.json"));
+        assert!(!matches(&list, "fixtures/keep.json"));
+
+        // Like with git, nothing in an excluded directory can be re-included.
+        let list = patterns(&["fixtures/", "!fixtures/keep.json"]);
+        assert!(matches(&list, "fixtures/keep.json"));
+
+        assert!(matches(&patterns(&["\\!important.txt"]), "!important.txt"));
+    }
+
+    #[test]
+    fn patterns_can_ignore_case() {
+        let list = patterns(&["*.PNG"]);
+        assert!(!matches(&list, "logo.png"));
+        assert!(Pattern::list_matches(
+            &list,
+            Path::new("logo.png"),
+            false,
+            MatchOptions {
+                case_sensitive: false
+            }
+        ));
+    }
+
+    #[test]
+    fn patterns_are_read_from_strings() {
+        let list: Vec<Pattern> = serde_yaml::from_str("[\"docs/\", \"!docs/keep.md\"]").unwrap();
+        assert!(list[1].is_negated());
+        assert_eq!(
+            serde_yaml::to_string(&list).unwrap().trim(),
+            "---\n- docs/\n- \"!docs/keep.md\""
+        );
+        assert!(serde_yaml::from_str::<Pattern>("\"/\"").is_err());
+        assert!(serde_yaml::from_str::<Pattern>("\"a/***\"").is_err());
+    }
+}
@@ -42,3 +42,16 @@ fn render_valid_template() {
         "name: foo, value: bar",
     );
 }
+
+#[test]
+fn render_nested_values() {
+    let template = "host: {{ database.host }}, services:{{#services}} {{.}}{{/services}}";
+
+    let values: crate::blueprint::Values =
+        serde_yaml::from_str("database:\n  host: localhost\nservices: [api, worker]").unwrap();
+
+    assert_eq!(
+        Mustache::new().render_template(template, values).unwrap(),
+        "host: localhost, services: api worker",
+    );
+}
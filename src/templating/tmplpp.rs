@@ -129,7 +129,16 @@ impl<'a> Template<'a> {
         regex.is_match(output)
     }
 
-    pub fn upgrade_to(&self, new_template: &Template, values: &Values, output: &str) -> String {
+    /// Renders the new template with the new values, keeping the contents of
+    /// the editable parts of the output, which was rendered from this
+    /// template with the given values.
+    pub fn upgrade_to(
+        &self,
+        new_template: &Template,
+        values: &Values,
+        new_values: &Values,
+        output: &str,
+    ) -> String {
         let regex = self.regex(values);
 
         let caps = regex.captures(output).unwrap();
@@ -142,7 +151,7 @@ impl<'a> Template<'a> {
                 Element::RawText(text) => result.push_str(text),
                 Element::Editable(name, _) => result.push_str(caps.name(name).unwrap().as_str()),
                 Element::Var(var_name) => {
-                    if let Some(value) = values.get(var_name) {
+                    if let Some(value) = new_values.get(var_name) {
                         result.push_str(value);
                     }
                 }
@@ -366,6 +375,19 @@ mod tests {
         );
     }
 
+    #[test]
+    fn render_nested_var() {
+        let template = Template::from_str("All mimsy were {{ foo.bar }} borogoves.").unwrap();
+
+        let mut values = Values::new();
+        values.insert("foo.bar", "the");
+
+        assert_eq!(
+            template.render_to_string(&values).unwrap(),
+            "All mimsy were the borogoves.",
+        );
+    }
+
     #[test]
     fn render_editable_block() {
         let template =
@@ -463,7 +485,7 @@ mod tests {
 
         assert_eq!(output, "All mimsy were the borogoves.");
 
-        let new_output = v1.upgrade_to(&v2, &Values::new(), &output);
+        let new_output = v1.upgrade_to(&v2, &Values::new(), &Values::new(), &output);
 
         assert_eq!(new_output, "All mimsy were my borogoves.");
     }
@@ -483,7 +505,7 @@ mod tests {
 
         assert_eq!(output, "All mimsy were the borogoves.");
 
-        let new_output = v1.upgrade_to(&v2, &values, &output);
+        let new_output = v1.upgrade_to(&v2, &values, &values, &output);
 
         assert_eq!(new_output, "All mimsy were my borogoves.");
     }
@@ -495,7 +517,7 @@ mod tests {
 
         let modified_output = "All mimsy bla bla bla the borogoves.";
 
-        let new_output = v1.upgrade_to(&v2, &Values::new(), modified_output);
+        let new_output = v1.upgrade_to(&v2, &Values::new(), &Values::new(), modified_output);
 
         assert_eq!(new_output, "All mimsy bla bla bla my borogoves.");
     }
@@ -564,7 +586,7 @@ mod tests {
     "#;
 
         assert_eq!(
-            v1.upgrade_to(&v2, &Values::new(), modified_output),
+            v1.upgrade_to(&v2, &Values::new(), &Values::new(), modified_output),
             expected_output_after_upgrade
         );
     }
@@ -0,0 +1,2 @@
+-- Created for {{ name }}
+CREATE TABLE orders (id INT);
@@ -0,0 +1,3 @@
+#!/usr/bin/env bash
+
+echo -n "$api_token" > script_output.md